@@ -0,0 +1,84 @@
+//! File helpers shared by the crates that keep their data in plain files,
+//! such as the user store and the world's zone files.
+
+use std::{fs::{File, OpenOptions}, io::Write, path::Path};
+
+/// Writes `data` to a temporary file next to `path` and renames it into
+/// place, so readers see either the old contents or the new, never a mix.
+pub fn write_file_atomic(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(format!(".tmp.{}", std::process::id()));
+    let tmp_path = path.with_file_name(tmp_name);
+
+    let mut tmp = File::create(&tmp_path)?;
+    let written = tmp.write_all(data).and_then(|_| tmp.sync_all());
+    if let Err(e) = written.and_then(|_| std::fs::rename(&tmp_path, path)) {
+        let _ = std::fs::remove_file(&tmp_path);
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// Takes a shared (read) or exclusive (write) advisory lock for `path`, on a
+/// `.lock` file alongside it, released when the returned file is dropped.
+/// The data file itself can't hold the lock, as writes replace it.
+pub fn lock_file(path: &Path, exclusive: bool) -> std::io::Result<File> {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".lock");
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path.with_file_name(name))?;
+    if exclusive {
+        file.lock()?;
+    } else {
+        file.lock_shared()?;
+    }
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn files(dir: &Path) -> Vec<String> {
+        let mut files: Vec<String> = std::fs::read_dir(dir).unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn test_write_file_atomic() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.json");
+        write_file_atomic(&path, b"first, and longer").unwrap();
+        write_file_atomic(&path, b"second").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "second");
+        assert_eq!(files(dir.path()), ["data.json"]);
+
+        // A failed write leaves nothing behind
+        let missing = dir.path().join("missing").join("data.json");
+        assert!(write_file_atomic(&missing, b"lost").is_err());
+        assert_eq!(files(dir.path()), ["data.json"]);
+    }
+
+    #[test]
+    fn test_lock_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.json");
+        let reader = lock_file(&path, false).unwrap();
+        let other_reader = lock_file(&path, false).unwrap();
+        assert_eq!(files(dir.path()), ["data.json.lock"]);
+
+        // A writer has to wait for the readers
+        let lock = dir.path().join("data.json.lock");
+        assert!(matches!(File::open(&lock).unwrap().try_lock(), Err(std::fs::TryLockError::WouldBlock)));
+        drop(reader);
+        drop(other_reader);
+        let _writer = lock_file(&path, true).unwrap();
+        assert!(matches!(File::open(&lock).unwrap().try_lock_shared(), Err(std::fs::TryLockError::WouldBlock)));
+    }
+}
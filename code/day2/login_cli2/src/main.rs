@@ -1,7 +1,24 @@
+mod transfer;
+use std::path::PathBuf;
 use clap::{Parser, Subcommand};
+use login_library2::{AuditAction, AuditEvent, AuditFilter, AuditLog, ConflictKind, ConflictPolicy, LastLogin, Role};
+use serde::Serialize;
+use transfer::Format;
 
 #[derive(Debug, Parser)]
 struct Cli {
+    /// Where users are stored: `json:PATH`, `sqlite:PATH`, or a path (`.db` selects SQLite)
+    #[arg(long, global = true, env = "MUD_USERS", default_value = "users.json")]
+    store: login_library2::StoreConfig,
+
+    /// Append-only log of account changes and logins, shared with the server
+    #[arg(long, global = true, env = "MUD_AUDIT_LOG", default_value = "audit.jsonl")]
+    audit_log: PathBuf,
+
+    /// JSON file of username and password rules; built-in defaults if omitted
+    #[arg(long, global = true, env = "MUD_ACCOUNT_POLICY")]
+    policy: Option<PathBuf>,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -9,15 +26,46 @@ struct Cli {
 #[derive(Debug, Subcommand)]
 enum Commands {
     /// List all users
-    List,
+    List {
+        /// Print JSON, including roles and last login, for scripts
+        #[arg(long)]
+        json: bool,
+    },
+    /// Export all users, with password hashes, as JSON or CSV
+    Export {
+        /// File to write; stdout if omitted
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+        /// Defaults to the output file's extension, or JSON
+        #[arg(long, value_enum)]
+        format: Option<Format>,
+        /// Also export 2FA secrets, in plaintext; without them imported users lose 2FA
+        #[arg(long)]
+        include_totp: bool,
+    },
+    /// Import users from JSON or CSV, either exported or with plain passwords
+    Import {
+        /// File to read, or `-` for stdin
+        #[arg(required = true)]
+        file: PathBuf,
+        /// Defaults to the file's extension, or JSON
+        #[arg(long, value_enum)]
+        format: Option<Format>,
+        /// What to do with usernames that already exist: abort, skip or overwrite
+        #[arg(long, default_value = "abort")]
+        on_conflict: ConflictPolicy,
+        /// Show what would happen, including conflicts, without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
     /// Add a user
     Add { 
         /// Username
         #[arg(required = true)]
         username: String, 
-        /// Password
-        #[arg(required = true)]
-        password: String 
+        /// Read the password from the first line of stdin instead of prompting
+        #[arg(long)]
+        password_stdin: bool,
     },
     /// Delete a user
     Delete { 
@@ -30,46 +78,247 @@ enum Commands {
         /// Username
         #[arg(required = true)]
         username: String, 
-        /// New password
+        /// Read the new password from the first line of stdin instead of prompting
+        #[arg(long)]
+        password_stdin: bool,
+    },
+    /// Grant a role (player, builder, moderator, admin) to a user
+    Grant {
+        /// Username
+        #[arg(required = true)]
+        username: String,
+        /// Role to grant
+        #[arg(required = true)]
+        role: login_library2::Role,
+    },
+    /// Revoke a role from a user
+    Revoke {
+        /// Username
         #[arg(required = true)]
-        password: String 
+        username: String,
+        /// Role to revoke
+        #[arg(required = true)]
+        role: login_library2::Role,
+    },
+    /// Enroll a user in two-factor authentication and print their otpauth secret
+    TotpEnroll {
+        /// Username
+        #[arg(required = true)]
+        username: String,
+        /// Name shown next to the account in the authenticator app
+        #[arg(long, default_value = "MUD")]
+        issuer: String,
+    },
+    /// Turn off two-factor authentication for a user
+    TotpDisable {
+        /// Username
+        #[arg(required = true)]
+        username: String,
     },
+    /// Show the audit log, optionally for one user and/or a time range
+    Audit {
+        /// Only show events for this user
+        #[arg(long)]
+        user: Option<String>,
+        /// Only show events at or after this time (Unix seconds, RFC 3339, or YYYY-MM-DD in UTC)
+        #[arg(long, value_parser = parse_time)]
+        since: Option<u64>,
+        /// Only show events before this time (same formats as --since)
+        #[arg(long, value_parser = parse_time)]
+        until: Option<u64>,
+    },
+}
+
+/// Gets a new password without it appearing on screen, in shell history or in
+/// `ps`: either prompted for twice on the terminal, or read from stdin for scripts.
+fn read_new_password(from_stdin: bool) -> anyhow::Result<String> {
+    if from_stdin {
+        let mut password = String::new();
+        std::io::stdin().read_line(&mut password)?;
+        return Ok(password.trim_end_matches(['\r', '\n']).to_string());
+    }
+    let password = rpassword::prompt_password("Password: ")
+        .map_err(|e| anyhow::anyhow!("Can't prompt for a password ({e}); use --password-stdin"))?;
+    if rpassword::prompt_password("Confirm password: ")? != password {
+        anyhow::bail!("Passwords do not match");
+    }
+    Ok(password)
+}
+
+/// Parses a time given as Unix seconds, an RFC 3339 timestamp, or a UTC date.
+fn parse_time(s: &str) -> Result<u64, String> {
+    if let Ok(secs) = s.parse::<u64>() {
+        return Ok(secs);
+    }
+    let time = if let Ok(time) = chrono::DateTime::parse_from_rfc3339(s) {
+        time.timestamp()
+    } else if let Ok(date) = chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        date.and_time(chrono::NaiveTime::MIN).and_utc().timestamp()
+    } else {
+        return Err(format!("expected Unix seconds, an RFC 3339 time or YYYY-MM-DD, got {s:?}"));
+    };
+    u64::try_from(time).map_err(|_| format!("{s} is before 1970"))
+}
+
+/// A user as shown by `list --json`; never includes password hashes.
+#[derive(Serialize)]
+struct ListEntry<'a> {
+    username: &'a str,
+    roles: Vec<Role>,
+    totp: bool,
+    last_login: Option<LastLogin>,
+}
+
+fn format_time(secs: u64) -> String {
+    i64::try_from(secs)
+        .ok()
+        .and_then(|secs| chrono::DateTime::from_timestamp(secs, 0))
+        .map_or_else(|| secs.to_string(), |time| time.format("%Y-%m-%d %H:%M:%S").to_string())
 }
 
 fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
 
+    let mut users = login_library2::LoginManager::open(&cli.store)?;
+    if let Some(path) = &cli.policy {
+        users.set_policy(login_library2::AccountPolicy::load(path)?);
+    }
+    let audit = AuditLog::new(&cli.audit_log);
+    // Changes made here are attributed to whoever ran the command
+    let actor = std::env::var("USER").unwrap_or_else(|_| "operator".to_string());
+    let record = |username: &str, action: AuditAction| {
+        audit.record(&AuditEvent::new(username, action).actor(&actor).source("login_cli2"))
+    };
+
     match cli.command {
-        Commands::List => {
-            let users = login_library2::LoginManager::new()?;
-            if users.users.is_empty() {
+        Commands::List { json: true } => {
+            let all_users = users.users()?;
+            let entries: Vec<ListEntry> = all_users.iter().map(|user| ListEntry {
+                username: &user.username,
+                roles: user.roles().iter().copied().collect(),
+                totp: user.requires_totp(),
+                last_login: user.last_login().cloned(),
+            }).collect();
+            println!("{}", serde_json::to_string_pretty(&entries)?);
+        }
+        Commands::List { json: false } => {
+            let all_users = users.users()?;
+            if all_users.is_empty() {
                 println!("No users found");
                 return Ok(());
             }
-            users.users.iter().for_each(|u| println!("{}", u.username));
+            for user in &all_users {
+                let roles: Vec<String> = user.roles().iter().map(|r| r.to_string()).collect();
+                let last_login = user.last_login().map_or("never".to_string(), |l| format_time(l.at));
+                println!("{} ({}), last login {}", user.username, roles.join(", "), last_login);
+            }
+        }
+        Commands::Export { output, format, include_totp } => {
+            let format = format.unwrap_or_else(|| output.as_deref().map_or(Format::Json, Format::for_path));
+            let records = users.export_users(include_totp)?;
+            let count = records.len();
+            match output {
+                Some(path) => {
+                    transfer::write_records(transfer::create_private(&path)?, format, records)?;
+                    eprintln!("Exported {} users to {}", count, path.display());
+                }
+                None => transfer::write_records(std::io::stdout().lock(), format, records)?,
+            }
+        }
+        Commands::Import { file, format, on_conflict, dry_run } => {
+            let records = if file.as_os_str() == "-" {
+                transfer::read_records(std::io::stdin().lock(), format.unwrap_or(Format::Json))?
+            } else {
+                transfer::read_records(std::fs::File::open(&file)?, format.unwrap_or_else(|| Format::for_path(&file)))?
+            };
+            let report = users.import_users(records, on_conflict, dry_run)?;
+            for conflict in &report.conflicts {
+                match conflict.kind {
+                    ConflictKind::Exists => println!("Conflict: user {} already exists", conflict.username),
+                    ConflictKind::Repeated => println!("Conflict: user {} appears more than once", conflict.username),
+                }
+            }
+            if report.applied {
+                for username in &report.added {
+                    record(username, AuditAction::UserAdded)?;
+                }
+                for username in &report.replaced {
+                    record(username, AuditAction::UserReplaced)?;
+                }
+            }
+            let verb = if report.applied { "" } else { "would be " };
+            println!(
+                "{} users {verb}added, {} {verb}replaced, {} {verb}skipped",
+                report.added.len(), report.replaced.len(), report.skipped.len()
+            );
+            if !dry_run && !report.applied {
+                anyhow::bail!("Import aborted because of conflicts; nothing was changed (see --on-conflict)");
+            }
         }
-        Commands::Add { username, password } => {
-            let mut users = login_library2::LoginManager::new()?;
-            users.add_user(&username, &password);
-            users.save()?;
+        Commands::Add { username, password_stdin } => {
+            let password = read_new_password(password_stdin)?;
+            users.add_user(&username, &password)?;
+            record(&username, AuditAction::UserAdded)?;
             println!("User {} added", username);
         }
         Commands::Delete { username } => {
-            let mut users = login_library2::LoginManager::new()?;
-            users.users.retain(|u| u.username != username);
-            users.save()?;
+            users.remove_user(&username)?;
+            record(&username, AuditAction::UserDeleted)?;
             println!("User {} deleted", username);
         }
-        Commands::Update { username, password } => {
-            let mut users = login_library2::LoginManager::new()?;
-            if let Some(user) = users.users.iter_mut().find(|u| u.username == username) {
-                *user = login_library2::User::new(&username, &password);
-                users.save()?;
-                println!("User {} updated", username);
+        Commands::Update { username, password_stdin } => {
+            let password = read_new_password(password_stdin)?;
+            users.update_password(&username, &password)?;
+            record(&username, AuditAction::PasswordChanged)?;
+            println!("User {} updated", username);
+        }
+        Commands::Grant { username, role } => {
+            if users.grant_role(&username, role)? {
+                record(&username, AuditAction::RoleGranted { role })?;
+                println!("Granted {} to {}", role, username);
+            } else {
+                println!("User {} already has role {}", username, role);
+            }
+        }
+        Commands::Revoke { username, role } => {
+            if users.revoke_role(&username, role)? {
+                record(&username, AuditAction::RoleRevoked { role })?;
+                println!("Revoked {} from {}", role, username);
+            } else {
+                println!("User {} does not have role {}", username, role);
+            }
+        }
+        Commands::TotpEnroll { username, issuer } => {
+            let totp = users.enroll_totp(&username)?;
+            record(&username, AuditAction::TotpEnrolled)?;
+            println!("Two-factor authentication enabled for {}", username);
+            println!("Secret: {}", totp.secret());
+            println!("{}", totp.otpauth_uri(&issuer, &username));
+        }
+        Commands::TotpDisable { username } => {
+            if users.disable_totp(&username)? {
+                record(&username, AuditAction::TotpDisabled)?;
+                println!("Two-factor authentication disabled for {}", username);
             } else {
-                println!("User {} not found", username);
+                println!("User {} does not use two-factor authentication", username);
             }
-        }        
+        }
+        Commands::Audit { user, since, until } => {
+            let events = audit.query(&AuditFilter { username: user, since, until })?;
+            if events.is_empty() {
+                println!("No matching events");
+            }
+            for event in &events {
+                let mut line = format!("{} {}: {}", format_time(event.at), event.username, event.action);
+                if let Some(actor) = &event.actor {
+                    line.push_str(&format!(" by {actor}"));
+                }
+                if let Some(source) = &event.source {
+                    line.push_str(&format!(" from {source}"));
+                }
+                println!("{line}");
+            }
+        }
 
     }
 
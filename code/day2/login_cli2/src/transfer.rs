@@ -0,0 +1,226 @@
+use std::{io::{Read, Write}, path::Path};
+use clap::ValueEnum;
+use login_library2::{LastLogin, Role, UserRecord};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    Json,
+    Csv,
+}
+
+impl Format {
+    /// Guesses from the file extension, defaulting to JSON.
+    pub fn for_path(path: &Path) -> Format {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("csv") => Format::Csv,
+            _ => Format::Json,
+        }
+    }
+}
+
+/// One CSV line. Only `username` and one of `password`/`password_hash` are
+/// required, so a cohort can be onboarded from a two-column spreadsheet.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct CsvRow {
+    username: String,
+    password: Option<String>,
+    password_hash: Option<String>,
+    /// Separated by spaces, commas or semicolons
+    roles: String,
+    totp_secret: Option<String>,
+    last_login: Option<u64>,
+    last_login_from: Option<String>,
+}
+
+/// The columns [`CsvRow`] understands.
+const CSV_COLUMNS: [&str; 7] = ["username", "password", "password_hash", "roles", "totp_secret", "last_login", "last_login_from"];
+
+impl From<UserRecord> for CsvRow {
+    fn from(record: UserRecord) -> Self {
+        let roles: Vec<String> = record.roles.iter().map(|r| r.to_string()).collect();
+        Self {
+            username: record.username,
+            password: record.password,
+            password_hash: record.password_hash,
+            roles: roles.join(" "),
+            totp_secret: record.totp_secret,
+            last_login: record.last_login.as_ref().map(|l| l.at),
+            last_login_from: record.last_login.and_then(|l| l.source),
+        }
+    }
+}
+
+impl TryFrom<CsvRow> for UserRecord {
+    type Error = anyhow::Error;
+
+    fn try_from(row: CsvRow) -> anyhow::Result<Self> {
+        let roles = row.roles
+            .split(|c: char| c.is_whitespace() || c == ',' || c == ';')
+            .filter(|r| !r.is_empty())
+            .map(|r| r.parse::<Role>())
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            username: row.username,
+            password: row.password,
+            password_hash: row.password_hash,
+            roles,
+            totp_secret: row.totp_secret,
+            last_login: row.last_login.map(|at| LastLogin { at, source: row.last_login_from }),
+        })
+    }
+}
+
+pub fn read_records(reader: impl Read, format: Format) -> anyhow::Result<Vec<UserRecord>> {
+    match format {
+        Format::Json => Ok(serde_json::from_reader(reader)?),
+        Format::Csv => {
+            let mut reader = csv::Reader::from_reader(reader);
+            // Every column is optional, so a misspelt one would otherwise be ignored
+            let headers = reader.headers()?;
+            if let Some(unknown) = headers.iter().find(|h| !CSV_COLUMNS.contains(h)) {
+                anyhow::bail!("line 1: unknown column {:?} (expected {})", unknown, CSV_COLUMNS.join(", "));
+            }
+            if !headers.iter().any(|h| h == "username") {
+                anyhow::bail!("line 1: there is no username column");
+            }
+            let mut records = Vec::new();
+            for (i, row) in reader.deserialize::<CsvRow>().enumerate() {
+                // Line 1 is the header
+                let record = row.map_err(anyhow::Error::from)
+                    .and_then(UserRecord::try_from)
+                    .map_err(|e| anyhow::anyhow!("line {}: {}", i + 2, e))?;
+                records.push(record);
+            }
+            Ok(records)
+        }
+    }
+}
+
+pub fn write_records(mut writer: impl Write, format: Format, records: Vec<UserRecord>) -> anyhow::Result<()> {
+    match format {
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut writer, &records)?;
+            writeln!(writer)?;
+        }
+        Format::Csv => {
+            let mut csv = csv::Writer::from_writer(writer);
+            for record in records {
+                csv.serialize(CsvRow::from(record))?;
+            }
+            csv.flush()?;
+        }
+    }
+    Ok(())
+}
+
+/// Creates (or truncates) an export file readable only by its owner, since
+/// it holds password hashes. An existing file's permissions are tightened too.
+pub fn create_private(path: &Path) -> std::io::Result<std::fs::File> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        let file = options.open(path)?;
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+        Ok(file)
+    }
+    #[cfg(not(unix))]
+    options.open(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(username: &str) -> UserRecord {
+        UserRecord {
+            username: username.to_string(),
+            password: None,
+            password_hash: Some("$argon2id$v=19$m=256,t=1,p=1$c2FsdA$aGFzaA".to_string()),
+            roles: [Role::Player].into(),
+            totp_secret: None,
+            last_login: None,
+        }
+    }
+
+    fn round_trip(format: Format, records: Vec<UserRecord>) -> (String, Vec<UserRecord>) {
+        let mut written = Vec::new();
+        write_records(&mut written, format, records).unwrap();
+        let read = read_records(written.as_slice(), format).unwrap();
+        (String::from_utf8(written).unwrap(), read)
+    }
+
+    #[test]
+    fn test_round_trips() {
+        let mut alice = record("alice");
+        alice.roles = [Role::Player, Role::Builder, Role::Admin].into();
+        alice.totp_secret = Some("JBSWY3DPEHPK3PXP".to_string());
+        alice.last_login = Some(LastLogin { at: 1234, source: Some("10.0.0.1:5000".to_string()) });
+        let mut bob = record("bob");
+        bob.password = Some("with, a comma and \"quotes\"".to_string());
+        bob.password_hash = None;
+        let records = vec![alice, bob];
+
+        for format in [Format::Json, Format::Csv] {
+            let (_, read) = round_trip(format, records.clone());
+            assert_eq!(read, records, "{format:?}");
+        }
+        // Fields with commas are quoted, and roles share one column
+        let (csv, _) = round_trip(Format::Csv, records);
+        assert!(csv.contains(r#""with, a comma and ""quotes""""#), "{csv}");
+        assert!(csv.contains("player builder admin"), "{csv}");
+    }
+
+    #[test]
+    fn test_csv_from_a_spreadsheet() {
+        let csv = "username,password,roles\nalice,one two three,\"builder, moderator\"\nbob,four five six,admin;player\ncarol,seven eight nine,\n";
+        let records = read_records(csv.as_bytes(), Format::Csv).unwrap();
+        let roles: Vec<Vec<Role>> = records.iter().map(|r| r.roles.iter().copied().collect()).collect();
+        assert_eq!(roles, [vec![Role::Builder, Role::Moderator], vec![Role::Player, Role::Admin], vec![]]);
+        assert_eq!(records[0].password.as_deref(), Some("one two three"));
+        assert_eq!(records[2].password_hash, None);
+    }
+
+    #[test]
+    fn test_csv_errors_name_the_line() {
+        let error = |csv: &str| read_records(csv.as_bytes(), Format::Csv).unwrap_err().to_string();
+        assert_eq!(
+            error("username,pasword\nalice,x\n"),
+            "line 1: unknown column \"pasword\" (expected username, password, password_hash, roles, totp_secret, last_login, last_login_from)",
+        );
+        assert_eq!(error("password,roles\nx,player\n"), "line 1: there is no username column");
+        assert!(error("username,roles\nalice,player\nbob,wizard\n").starts_with("line 3: "));
+        assert!(error("username,last_login\nalice,yesterday\n").starts_with("line 2: "));
+    }
+
+    #[test]
+    fn test_format_for_path() {
+        assert_eq!(Format::for_path(Path::new("users.CSV")), Format::Csv);
+        assert_eq!(Format::for_path(Path::new("users.json")), Format::Json);
+        assert_eq!(Format::for_path(Path::new("users")), Format::Json);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_exports_are_private() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+
+        let path = dir.path().join("new.json");
+        write_records(create_private(&path).unwrap(), Format::Json, vec![record("alice")]).unwrap();
+        assert_eq!(mode(&path), 0o600);
+
+        // An existing file is tightened and replaced
+        let path = dir.path().join("old.json");
+        std::fs::write(&path, "old contents that are longer than the new ones").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        write_records(create_private(&path).unwrap(), Format::Json, vec![]).unwrap();
+        assert_eq!(mode(&path), 0o600);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "[]\n");
+    }
+}
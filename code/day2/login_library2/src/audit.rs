@@ -0,0 +1,191 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+use serde::{Deserialize, Serialize};
+use crate::{unix_now, LoginError, Role};
+
+/// Something that happened to an account.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum AuditAction {
+    UserAdded,
+    UserDeleted,
+    /// Overwritten by `login_cli2 import`
+    UserReplaced,
+    PasswordChanged,
+    /// The current password given to change it was wrong
+    PasswordChangeFailed,
+    RegistrationRefused { reason: String },
+    RoleGranted { role: Role },
+    RoleRevoked { role: Role },
+    TotpEnrolled,
+    TotpDisabled,
+    LoginSucceeded,
+    LoginFailed { reason: String },
+    LockedOut,
+}
+
+impl std::fmt::Display for AuditAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuditAction::UserAdded => write!(f, "user added"),
+            AuditAction::UserDeleted => write!(f, "user deleted"),
+            AuditAction::UserReplaced => write!(f, "user replaced by import"),
+            AuditAction::PasswordChanged => write!(f, "password changed"),
+            AuditAction::PasswordChangeFailed => write!(f, "password change failed (wrong current password)"),
+            AuditAction::RegistrationRefused { reason } => write!(f, "registration refused ({reason})"),
+            AuditAction::RoleGranted { role } => write!(f, "role {role} granted"),
+            AuditAction::RoleRevoked { role } => write!(f, "role {role} revoked"),
+            AuditAction::TotpEnrolled => write!(f, "two-factor authentication enrolled"),
+            AuditAction::TotpDisabled => write!(f, "two-factor authentication disabled"),
+            AuditAction::LoginSucceeded => write!(f, "login succeeded"),
+            AuditAction::LoginFailed { reason } => write!(f, "login failed ({reason})"),
+            AuditAction::LockedOut => write!(f, "locked out"),
+        }
+    }
+}
+
+/// One line of the audit log.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AuditEvent {
+    /// Seconds since the Unix epoch
+    pub at: u64,
+    /// The account the event is about
+    pub username: String,
+    #[serde(flatten)]
+    pub action: AuditAction,
+    /// Who made the change, if not the user themselves (e.g. an operator running `login_cli2`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub actor: Option<String>,
+    /// Where the request came from, such as the client's address
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+}
+
+impl AuditEvent {
+    /// An event that happened now.
+    pub fn new(username: &str, action: AuditAction) -> Self {
+        Self { at: unix_now(), username: username.to_string(), action, actor: None, source: None }
+    }
+
+    pub fn actor(mut self, actor: impl Into<String>) -> Self {
+        self.actor = Some(actor.into());
+        self
+    }
+
+    pub fn source(mut self, source: impl ToString) -> Self {
+        self.source = Some(source.to_string());
+        self
+    }
+}
+
+/// Which events to return from [`AuditLog::query`]. Empty matches everything.
+#[derive(Debug, Clone, Default)]
+pub struct AuditFilter {
+    pub username: Option<String>,
+    /// Inclusive lower bound, in seconds since the epoch
+    pub since: Option<u64>,
+    /// Exclusive upper bound, in seconds since the epoch
+    pub until: Option<u64>,
+}
+
+impl AuditFilter {
+    pub fn matches(&self, event: &AuditEvent) -> bool {
+        self.username.as_ref().is_none_or(|u| *u == event.username)
+            && self.since.is_none_or(|since| event.at >= since)
+            && self.until.is_none_or(|until| event.at < until)
+    }
+}
+
+/// An append-only log of account events, one JSON object per line.
+///
+/// Each event is written with a single append under an exclusive lock, so
+/// the CLI and a running server can share one file.
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    path: PathBuf,
+}
+
+impl AuditLog {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn record(&self, event: &AuditEvent) -> Result<(), LoginError> {
+        let mut line = serde_json::to_string(event)?;
+        line.push('\n');
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        file.lock()?;
+        file.write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    /// Returns the matching events, oldest first. A missing log is empty.
+    pub fn query(&self, filter: &AuditFilter) -> Result<Vec<AuditEvent>, LoginError> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e.into()),
+        };
+        file.lock_shared()?;
+        let mut events = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let event: AuditEvent = serde_json::from_str(&line)?;
+            if filter.matches(&event) {
+                events.push(event);
+            }
+        }
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: u64, username: &str, action: AuditAction) -> AuditEvent {
+        AuditEvent { at: time, ..AuditEvent::new(username, action) }
+    }
+
+    #[test]
+    fn test_line_format() {
+        let event = at(100, "alice", AuditAction::RoleGranted { role: Role::Admin }).actor("root");
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"at":100,"username":"alice","event":"role_granted","role":"admin","actor":"root"}"#
+        );
+    }
+
+    #[test]
+    fn test_record_and_query() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = AuditLog::new(dir.path().join("audit.jsonl"));
+        assert!(log.query(&AuditFilter::default()).unwrap().is_empty());
+
+        let events = [
+            at(100, "alice", AuditAction::UserAdded).actor("root"),
+            at(200, "alice", AuditAction::LoginFailed { reason: "bad password".to_string() }).source("127.0.0.1:5000"),
+            at(300, "bob", AuditAction::LoginSucceeded).source("127.0.0.1:5001"),
+            at(400, "alice", AuditAction::LockedOut).source("127.0.0.1:5002"),
+        ];
+        for event in &events {
+            log.record(event).unwrap();
+        }
+
+        assert_eq!(log.query(&AuditFilter::default()).unwrap(), events);
+        let alice = AuditFilter { username: Some("alice".to_string()), ..Default::default() };
+        assert_eq!(log.query(&alice).unwrap().len(), 3);
+        let window = AuditFilter { since: Some(200), until: Some(400), ..Default::default() };
+        assert_eq!(log.query(&window).unwrap(), events[1..3]);
+    }
+}
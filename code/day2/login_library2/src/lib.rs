@@ -1,7 +1,85 @@
-use std::path::Path;
+mod audit;
+mod lockout;
+mod policy;
+mod role;
+mod store;
+mod totp;
+mod transfer;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+pub use audit::{AuditAction, AuditEvent, AuditFilter, AuditLog};
+pub use lockout::{Attempt, LockoutPolicy, LoginBlock, LoginThrottle, ThrottleSnapshot};
+pub use policy::{AccountPolicy, PasswordPolicy, UsernamePolicy};
+pub use role::Role;
+pub use store::{JsonFileStore, MemoryStore, StoreConfig, UserStore};
+pub use totp::{Totp, TOTP_DIGITS, TOTP_STEP_SECS};
+pub use transfer::{ConflictKind, ConflictPolicy, ImportConflict, ImportReport, UserRecord};
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteStore;
+
+use argon2::{
+    password_hash::{rand_core::OsRng, SaltString},
+    Algorithm, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier, Version,
+};
 use serde::{Deserialize, Serialize};
+use std::{collections::BTreeSet, sync::{Mutex, MutexGuard, OnceLock, PoisonError}};
+
+/// Seconds since the Unix epoch, the unit used for all timestamps in this crate.
+pub fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Argon2id cost parameters used when hashing new passwords.
+///
+/// Existing hashes keep the parameters they were created with (they are stored
+/// in the PHC string), so raising these only affects new and rehashed passwords.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HashParams {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
 
-fn hash_password(password: &str) -> String {
+impl Default for HashParams {
+    fn default() -> Self {
+        Self {
+            memory_kib: Params::DEFAULT_M_COST,
+            iterations: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+impl HashParams {
+    fn hasher(&self) -> Result<Argon2<'static>, LoginError> {
+        let params = Params::new(self.memory_kib, self.iterations, self.parallelism, None)?;
+        Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+    }
+}
+
+/// Hashes a password with Argon2id and a random salt, returning a PHC string
+/// such as `$argon2id$v=19$m=19456,t=2,p=1$<salt>$<hash>`.
+fn hash_password(password: &str, params: &HashParams) -> Result<String, LoginError> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = params.hasher()?.hash_password(password.as_bytes(), &salt)?;
+    Ok(hash.to_string())
+}
+
+/// Checks a password against a PHC string, using the parameters stored in it.
+fn verify_hash(hash: &str, password: &str) -> bool {
+    let Ok(hash) = PasswordHash::new(hash) else {
+        return false;
+    };
+    Argon2::default().verify_password(password.as_bytes(), &hash).is_ok()
+}
+
+/// The original unsalted format: an uppercase SHA-256 hex digest.
+/// Only used to verify accounts that have not been migrated yet.
+fn legacy_hash_password(password: &str) -> String {
     use sha2::Digest;
     let mut hasher = sha2::Sha256::new();
     hasher.update(password);
@@ -12,18 +90,106 @@ fn hash_password(password: &str) -> String {
 pub struct User {
     pub username: String,
     password: String,
+    #[serde(default = "role::default_roles")]
+    roles: BTreeSet<Role>,
+    /// Set once the user has enrolled in two-factor authentication
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    totp: Option<Totp>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_login: Option<LastLogin>,
+}
+
+/// When and where a user last logged in successfully.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LastLogin {
+    /// Seconds since the Unix epoch
+    pub at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
 }
 
 impl User {
-    pub fn new(username: &str, password: &str) -> Self {
-        Self {
+    pub fn new(username: &str, password: &str) -> Result<Self, LoginError> {
+        Self::with_params(username, password, &HashParams::default())
+    }
+
+    pub fn with_params(username: &str, password: &str, params: &HashParams) -> Result<Self, LoginError> {
+        Ok(Self {
             username: username.to_string(),
-            password: hash_password(password),
-        }
+            password: hash_password(password, params)?,
+            roles: role::default_roles(),
+            totp: None,
+            last_login: None,
+        })
+    }
+
+    pub fn roles(&self) -> &BTreeSet<Role> {
+        &self.roles
+    }
+
+    /// Admins implicitly hold every role.
+    pub fn has_role(&self, role: Role) -> bool {
+        self.roles.contains(&role) || self.roles.contains(&Role::Admin)
+    }
+
+    /// Returns false if the user already had the role.
+    pub fn grant(&mut self, role: Role) -> bool {
+        self.roles.insert(role)
+    }
+
+    /// Returns false if the user didn't have the role. Players can't be demoted
+    /// below [`Role::Player`].
+    pub fn revoke(&mut self, role: Role) -> bool {
+        role != Role::Player && self.roles.remove(&role)
+    }
+
+    pub fn last_login(&self) -> Option<&LastLogin> {
+        self.last_login.as_ref()
+    }
+
+    /// True if logging in also needs a one-time code.
+    pub fn requires_totp(&self) -> bool {
+        self.totp.is_some()
+    }
+
+    /// Replaces the stored hash, keeping everything else about the user.
+    pub fn set_password(&mut self, password: &str, params: &HashParams) -> Result<(), LoginError> {
+        self.password = hash_password(password, params)?;
+        Ok(())
+    }
+
+    /// Legacy entries are bare hex digests; everything we write now is a PHC string.
+    fn is_legacy(&self) -> bool {
+        !self.password.starts_with('$')
     }
 
     pub fn verify_password(&self, password: &str) -> bool {
-        self.password == hash_password(password)
+        if self.is_legacy() {
+            return self.password == legacy_hash_password(password);
+        }
+        verify_hash(&self.password, password)
+    }
+
+    /// True if the stored hash is a legacy digest, or an Argon2 hash created
+    /// with different parameters than `params`.
+    pub fn needs_rehash(&self, params: &HashParams) -> bool {
+        if self.is_legacy() {
+            return true;
+        }
+        let Ok(hash) = PasswordHash::new(&self.password) else {
+            return true;
+        };
+        if hash.algorithm != Algorithm::Argon2id.ident() {
+            return true;
+        }
+        match Params::try_from(&hash) {
+            Ok(current) => {
+                current.m_cost() != params.memory_kib
+                    || current.t_cost() != params.iterations
+                    || current.p_cost() != params.parallelism
+            }
+            Err(_) => true,
+        }
     }
 }
 
@@ -33,41 +199,257 @@ pub enum LoginError {
     IoError(#[from] std::io::Error),
     #[error("Serialization error: {0}")]
     SerializationError(#[from] serde_json::Error),
+    #[error("Password hashing error: {0}")]
+    HashError(#[from] argon2::password_hash::Error),
+    #[error("Invalid hashing parameters: {0}")]
+    HashParamsError(#[from] argon2::Error),
+    #[cfg(feature = "sqlite")]
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] rusqlite::Error),
+    #[error("Unsupported user store: {0}")]
+    UnsupportedStore(String),
+    #[error("User {0} already exists")]
+    DuplicateUser(String),
+    #[error("Unknown user: {0}")]
+    UnknownUser(String),
+    #[error("Invalid username: {0}")]
+    InvalidUsername(String),
+    #[error("Password policy violation: {0}")]
+    PasswordPolicyViolation(String),
+    #[error("Invalid user record: {0}")]
+    InvalidRecord(String),
+    #[error("Unknown role: {0} (expected one of player, builder, moderator, admin)")]
+    UnknownRole(String),
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Users and the rules for them. Every method takes `&self`, so one manager
+/// can be shared between threads: the store is locked for each store call,
+/// but never while a password is being hashed or checked.
 pub struct LoginManager {
-    pub users: Vec<User>,
+    store: Mutex<Box<dyn UserStore>>,
+    hash_params: HashParams,
+    policy: AccountPolicy,
+    /// Checked against when a username doesn't exist; made on first use
+    dummy_hash: OnceLock<String>,
 }
 
 impl LoginManager {
+    /// Opens the default store: `users.json` in the current directory.
     pub fn new() -> Result<Self, LoginError> {
-        let path = Path::new("users.json");
-        if path.exists() {
-            let data = std::fs::read_to_string(path)
-                .map_err(LoginError::IoError)?;
-            let users: Vec<User> = serde_json::from_str(&data)
-                .map_err(LoginError::SerializationError)?;
-            Ok(Self { users })
-        } else {
-            Ok(Self { users: vec![] })
+        Self::open(&StoreConfig::default())
+    }
+
+    pub fn open(config: &StoreConfig) -> Result<Self, LoginError> {
+        Ok(Self::from_boxed_store(config.open()?))
+    }
+
+    pub fn with_store(store: impl UserStore + 'static) -> Self {
+        Self::from_boxed_store(Box::new(store))
+    }
+
+    fn from_boxed_store(store: Box<dyn UserStore>) -> Self {
+        Self {
+            store: Mutex::new(store),
+            hash_params: HashParams::default(),
+            policy: AccountPolicy::default(),
+            dummy_hash: OnceLock::new(),
         }
     }
 
-    pub fn save(&self) -> Result<(), LoginError> {
-        let data = serde_json::to_string_pretty(&self.users)
-            .map_err(LoginError::SerializationError)?;
-        std::fs::write("users.json", data).map_err(LoginError::IoError)?;
-        Ok(())
+    fn store(&self) -> MutexGuard<'_, Box<dyn UserStore>> {
+        // Each store call persists its change before returning, so a panic
+        // elsewhere can't have left the store half-updated
+        self.store.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Checks `password` against a throwaway hash, so that a username that
+    /// doesn't exist takes as long to reject as a wrong password.
+    fn verify_dummy(&self, password: &str) {
+        let hash = self.dummy_hash.get_or_init(|| hash_password("", &self.hash_params).unwrap_or_default());
+        verify_hash(hash, password);
+    }
+
+    /// Swaps a user's hash for `new`, unless it has changed from `old` since
+    /// it was read. Returns the updated user, or None if it had changed.
+    fn replace_hash(&self, username: &str, old: &str, new: String) -> Result<Option<User>, LoginError> {
+        let mut store = self.store();
+        let Some(mut user) = store.get(username)? else {
+            return Err(LoginError::UnknownUser(username.to_string()));
+        };
+        if user.password != old {
+            return Ok(None);
+        }
+        user.password = new;
+        store.update(user.clone())?;
+        Ok(Some(user))
+    }
+
+    /// Changes the parameters used for new hashes. Users whose stored hash
+    /// uses different parameters are rehashed on their next successful login.
+    pub fn set_hash_params(&mut self, params: HashParams) {
+        self.hash_params = params;
+        self.dummy_hash = OnceLock::new();
+    }
+
+    /// Changes the rules for new usernames and passwords.
+    pub fn set_policy(&mut self, policy: AccountPolicy) {
+        self.policy = policy;
+    }
+
+    pub fn policy(&self) -> &AccountPolicy {
+        &self.policy
+    }
+
+    pub fn users(&self) -> Result<Vec<User>, LoginError> {
+        self.store().load()
+    }
+
+    /// The user as stored now, e.g. to pick up role changes made since they logged in.
+    pub fn user(&self, username: &str) -> Result<Option<User>, LoginError> {
+        self.store().get(username)
+    }
+
+    /// Creates a new user and returns it. Fails with [`LoginError::DuplicateUser`]
+    /// if the username is already taken, or a policy error if the username or
+    /// password isn't allowed.
+    pub fn add_user(&self, username: &str, password: &str) -> Result<User, LoginError> {
+        self.policy.username.check(username)?;
+        self.policy.password.check(username, password)?;
+        if self.store().get(username)?.is_some() {
+            return Err(LoginError::DuplicateUser(username.to_string()));
+        }
+        // If someone else takes the name while this hashes, the insert fails
+        let user = User::with_params(username, password, &self.hash_params)?;
+        self.store().insert(user.clone())?;
+        Ok(user)
+    }
+
+    pub fn update_password(&self, username: &str, password: &str) -> Result<(), LoginError> {
+        self.policy.password.check(username, password)?;
+        let password = hash_password(password, &self.hash_params)?;
+        let mut store = self.store();
+        let Some(mut user) = store.get(username)? else {
+            return Err(LoginError::UnknownUser(username.to_string()));
+        };
+        user.password = password;
+        store.update(user)
+    }
+
+    /// Changes a user's own password, which requires the current one.
+    /// Returns false if `old_password` is wrong, or if the password was
+    /// changed some other way while this one was being checked.
+    pub fn change_password(&self, username: &str, old_password: &str, new_password: &str) -> Result<bool, LoginError> {
+        self.policy.password.check(username, new_password)?;
+        let user = self.store().get(username)?;
+        let Some(user) = user else {
+            return Err(LoginError::UnknownUser(username.to_string()));
+        };
+        if !user.verify_password(old_password) {
+            return Ok(false);
+        }
+        let password = hash_password(new_password, &self.hash_params)?;
+        Ok(self.replace_hash(username, &user.password, password)?.is_some())
+    }
+
+    pub fn remove_user(&self, username: &str) -> Result<(), LoginError> {
+        self.store().delete(username)
+    }
+
+    /// Grants a role, returning false if the user already had it.
+    pub fn grant_role(&self, username: &str, role: Role) -> Result<bool, LoginError> {
+        self.change_roles(username, |user| user.grant(role))
+    }
+
+    /// Revokes a role, returning false if the user didn't have it.
+    pub fn revoke_role(&self, username: &str, role: Role) -> Result<bool, LoginError> {
+        self.change_roles(username, |user| user.revoke(role))
+    }
+
+    fn change_roles(&self, username: &str, change: impl FnOnce(&mut User) -> bool) -> Result<bool, LoginError> {
+        let mut store = self.store();
+        let Some(mut user) = store.get(username)? else {
+            return Err(LoginError::UnknownUser(username.to_string()));
+        };
+        if !change(&mut user) {
+            return Ok(false);
+        }
+        store.update(user)?;
+        Ok(true)
+    }
+
+    /// Enrolls a user in two-factor authentication with a fresh secret,
+    /// replacing any previous one. Show the result to the user exactly once.
+    pub fn enroll_totp(&self, username: &str) -> Result<Totp, LoginError> {
+        let mut store = self.store();
+        let Some(mut user) = store.get(username)? else {
+            return Err(LoginError::UnknownUser(username.to_string()));
+        };
+        let totp = Totp::generate();
+        user.totp = Some(totp.clone());
+        store.update(user)?;
+        Ok(totp)
     }
 
-    pub fn add_user(&mut self, username: &str, password: &str) {
-        let user = User::new(username, password);
-        self.users.push(user);
+    /// Turns two-factor authentication off, returning false if it wasn't on.
+    pub fn disable_totp(&self, username: &str) -> Result<bool, LoginError> {
+        let mut store = self.store();
+        let Some(mut user) = store.get(username)? else {
+            return Err(LoginError::UnknownUser(username.to_string()));
+        };
+        if user.totp.take().is_none() {
+            return Ok(false);
+        }
+        store.update(user)?;
+        Ok(true)
+    }
+
+    /// Checks a one-time code for a user who [requires](User::requires_totp) one,
+    /// as of `now` (seconds since the epoch). Users without 2FA never pass.
+    pub fn verify_totp(&self, username: &str, code: &str, now: u64) -> Result<bool, LoginError> {
+        let mut store = self.store();
+        let Some(mut user) = store.get(username)? else {
+            return Ok(false);
+        };
+        let Some(totp) = user.totp.as_mut() else {
+            return Ok(false);
+        };
+        if !totp.verify(code, now) {
+            return Ok(false);
+        }
+        // Remember the step so the same code can't be replayed
+        store.update(user)?;
+        Ok(true)
+    }
+
+    /// Remembers a completed login, for `login_cli2 list`.
+    pub fn record_login(&self, username: &str, at: u64, source: Option<String>) -> Result<(), LoginError> {
+        let mut store = self.store();
+        let Some(mut user) = store.get(username)? else {
+            return Err(LoginError::UnknownUser(username.to_string()));
+        };
+        user.last_login = Some(LastLogin { at, source });
+        store.update(user)
     }
 
-    pub fn verify_user(&self, username: &str, password: &str) -> Option<&User> {
-        self.users.iter().find(|user| user.username == username && user.verify_password(password))
+    /// Checks a username and password. On success, a user whose hash is out
+    /// of date (legacy SHA-256 or old Argon2 parameters) is transparently
+    /// rehashed and written back to the store. Unknown usernames take as long
+    /// to reject as wrong passwords, so timing doesn't reveal which exist.
+    pub fn verify_user(&self, username: &str, password: &str) -> Result<Option<User>, LoginError> {
+        let user = self.store().get(username)?;
+        let Some(user) = user else {
+            self.verify_dummy(password);
+            return Ok(None);
+        };
+        if !user.verify_password(password) {
+            return Ok(None);
+        }
+        if user.needs_rehash(&self.hash_params) {
+            let password = hash_password(password, &self.hash_params)?;
+            // Changed meanwhile: the login still counts, but keep the new hash
+            return Ok(Some(self.replace_hash(username, &user.password, password)?.unwrap_or(user)));
+        }
+        Ok(Some(user))
     }
 }
 
@@ -75,43 +457,317 @@ impl LoginManager {
 mod tests {
     use super::*;
 
+    /// Cheap parameters for tests that don't care about hash strength.
+    const FAST: HashParams = HashParams { memory_kib: 256, iterations: 1, parallelism: 1 };
+
+    /// A manager whose policy accepts the short passwords these tests use.
+    pub(crate) fn lenient_manager(store: impl UserStore + 'static) -> LoginManager {
+        let mut manager = LoginManager::with_store(store);
+        let password = PasswordPolicy { min_length: 1, deny_common: false, deny_username: false, ..Default::default() };
+        manager.set_policy(AccountPolicy { password, ..Default::default() });
+        manager
+    }
+
     #[test]
     fn test_hash_twice() {
         let password = "hunter2";
-        let hash1 = hash_password(password);
-        let hash2 = hash_password(password);
-        assert_eq!(hash1, hash2);
+        let hash1 = hash_password(password, &HashParams::default()).unwrap();
+        let hash2 = hash_password(password, &HashParams::default()).unwrap();
+        // Salted, so the same password never hashes to the same string
+        assert_ne!(hash1, hash2);
     }
 
     #[test]
     fn test_hashing_different_passwords() {
         let password1 = "hunter2";
         let password2 = "hunter3";
-        let hash1 = hash_password(password1);
-        let hash2 = hash_password(password2);
+        let hash1 = hash_password(password1, &HashParams::default()).unwrap();
+        let hash2 = hash_password(password2, &HashParams::default()).unwrap();
         assert_ne!(hash1, hash2);
     }
 
+    #[test]
+    fn test_hash_is_phc_string() {
+        let hash = hash_password("hunter2", &HashParams::default()).unwrap();
+        assert!(hash.starts_with("$argon2id$v=19$m=19456,t=2,p=1$"));
+    }
+
     #[test]
     fn test_user_creation() {
-        let user = User::new("alice", "password123");
+        let user = User::new("alice", "password123").unwrap();
         assert_eq!(user.username, "alice");
         assert_ne!(user.password, "password123");
     }
 
     #[test]
     fn test_verify_password() {
-        let user = User::new("bob", "secret");
+        let user = User::new("bob", "secret").unwrap();
         assert!(user.verify_password("secret"));
         assert!(!user.verify_password("wrong_password"));
     }
 
+    #[test]
+    fn test_verify_legacy_password() {
+        let user = User { username: "dave".to_string(), password: legacy_hash_password("password"), roles: role::default_roles(), totp: None, last_login: None };
+        assert!(user.verify_password("password"));
+        assert!(!user.verify_password("wrong_password"));
+        assert!(user.needs_rehash(&HashParams::default()));
+    }
+
+    #[test]
+    fn test_needs_rehash_on_param_change() {
+        let user = User::new("erin", "secret").unwrap();
+        assert!(!user.needs_rehash(&HashParams::default()));
+        let stronger = HashParams { iterations: 3, ..HashParams::default() };
+        assert!(user.needs_rehash(&stronger));
+    }
+
     #[test]
     fn test_login_manager_add_and_verify() {
-        let mut manager = LoginManager::new().unwrap();
-        manager.add_user("charlie", "mypassword");
-        assert!(manager.verify_user("charlie", "mypassword").is_some());
-        assert!(manager.verify_user("charlie", "wrongpassword").is_none());
-        assert!(manager.verify_user("unknown", "mypassword").is_none());
+        let manager = lenient_manager(MemoryStore::new());
+        manager.add_user("charlie", "mypassword").unwrap();
+        assert!(manager.verify_user("charlie", "mypassword").unwrap().is_some());
+        assert!(manager.verify_user("charlie", "wrongpassword").unwrap().is_none());
+        assert!(manager.dummy_hash.get().is_none());
+        assert!(manager.verify_user("unknown", "mypassword").unwrap().is_none());
+        // Unknown users are still checked against something
+        assert!(manager.dummy_hash.get().is_some_and(|hash| hash.starts_with("$argon2id$")));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_legacy_user_rehashed_on_login() {
+        let legacy = User { username: "frank".to_string(), password: legacy_hash_password("password"), roles: role::default_roles(), totp: None, last_login: None };
+        let manager = lenient_manager(MemoryStore::with_users(vec![legacy]));
+        assert!(manager.verify_user("frank", "wrong").unwrap().is_none());
+        assert!(manager.store().get("frank").unwrap().unwrap().is_legacy());
+
+        let user = manager.verify_user("frank", "password").unwrap().unwrap();
+        assert!(!user.is_legacy());
+        let stored = manager.store().get("frank").unwrap().unwrap();
+        assert!(stored.password.starts_with("$argon2id$"));
+        assert!(manager.verify_user("frank", "password").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_login_manager_enforces_policy() {
+        let manager = LoginManager::with_store(MemoryStore::new());
+        assert!(matches!(manager.add_user("alice", "one"), Err(LoginError::PasswordPolicyViolation(_))));
+        assert!(matches!(manager.add_user("a b", "correct horse"), Err(LoginError::InvalidUsername(_))));
+        manager.add_user("alice", "correct horse").unwrap();
+        assert!(matches!(manager.update_password("alice", "letmein"), Err(LoginError::PasswordPolicyViolation(_))));
+        assert!(matches!(
+            manager.change_password("alice", "correct horse", "alice123"),
+            Err(LoginError::PasswordPolicyViolation(_))
+        ));
+    }
+
+    #[test]
+    fn test_login_manager_rejects_duplicates() {
+        let manager = lenient_manager(MemoryStore::new());
+        manager.add_user("alice", "one").unwrap();
+        assert!(matches!(manager.add_user("alice", "two"), Err(LoginError::DuplicateUser(name)) if name == "alice"));
+        assert_eq!(manager.users().unwrap().len(), 1);
+        assert!(manager.verify_user("alice", "one").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_login_manager_update_and_remove() {
+        let manager = lenient_manager(MemoryStore::new());
+        manager.add_user("alice", "one").unwrap();
+        manager.update_password("alice", "two").unwrap();
+        assert!(manager.verify_user("alice", "one").unwrap().is_none());
+        assert!(manager.verify_user("alice", "two").unwrap().is_some());
+        assert!(matches!(manager.update_password("alice", ""), Err(LoginError::PasswordPolicyViolation(_))));
+        assert!(matches!(manager.update_password("bob", "two"), Err(LoginError::UnknownUser(_))));
+
+        manager.remove_user("alice").unwrap();
+        assert!(matches!(manager.remove_user("alice"), Err(LoginError::UnknownUser(_))));
+        assert!(manager.users().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_login_manager_change_password() {
+        let manager = lenient_manager(MemoryStore::new());
+        manager.add_user("alice", "one").unwrap();
+        assert!(!manager.change_password("alice", "wrong", "two").unwrap());
+        assert!(manager.verify_user("alice", "one").unwrap().is_some());
+        assert!(manager.change_password("alice", "one", "two").unwrap());
+        assert!(manager.verify_user("alice", "two").unwrap().is_some());
+        assert!(matches!(manager.change_password("alice", "two", ""), Err(LoginError::PasswordPolicyViolation(_))));
+    }
+
+    #[test]
+    fn test_login_manager_rejects_bad_usernames() {
+        let manager = lenient_manager(MemoryStore::new());
+        for bad in ["", "has space", "semi;colon", &"x".repeat(33)] {
+            assert!(matches!(manager.add_user(bad, "pw"), Err(LoginError::InvalidUsername(_))), "{bad:?}");
+        }
+        assert_eq!(manager.add_user("Good_Name-1", "pw").unwrap().username, "Good_Name-1");
+    }
+
+    #[test]
+    fn test_login_manager_totp() {
+        let manager = lenient_manager(MemoryStore::new());
+        manager.add_user("alice", "one").unwrap();
+        assert!(!manager.verify_user("alice", "one").unwrap().unwrap().requires_totp());
+        assert!(!manager.verify_totp("alice", "000000", 1000).unwrap());
+
+        let totp = manager.enroll_totp("alice").unwrap();
+        assert!(manager.verify_user("alice", "one").unwrap().unwrap().requires_totp());
+        let code = format!("{:06}", totp.code_at(1000).unwrap());
+        assert!(manager.verify_totp("alice", &code, 1000).unwrap());
+        // The used code was persisted, so it can't be replayed
+        assert!(!manager.verify_totp("alice", &code, 1000).unwrap());
+
+        assert!(manager.disable_totp("alice").unwrap());
+        assert!(!manager.disable_totp("alice").unwrap());
+        assert!(matches!(manager.enroll_totp("bob"), Err(LoginError::UnknownUser(_))));
+    }
+
+    #[test]
+    fn test_roles() {
+        let manager = lenient_manager(MemoryStore::new());
+        manager.add_user("alice", "one").unwrap();
+        let alice = manager.verify_user("alice", "one").unwrap().unwrap();
+        assert!(alice.has_role(Role::Player));
+        assert!(!alice.has_role(Role::Builder));
+
+        assert!(manager.grant_role("alice", Role::Builder).unwrap());
+        assert!(!manager.grant_role("alice", Role::Builder).unwrap());
+        let alice = manager.verify_user("alice", "one").unwrap().unwrap();
+        assert!(alice.has_role(Role::Builder));
+        assert!(!alice.has_role(Role::Moderator));
+
+        manager.grant_role("alice", Role::Admin).unwrap();
+        assert!(manager.verify_user("alice", "one").unwrap().unwrap().has_role(Role::Moderator));
+
+        assert!(manager.revoke_role("alice", Role::Admin).unwrap());
+        assert!(!manager.revoke_role("alice", Role::Player).unwrap());
+        assert!(matches!(manager.grant_role("bob", Role::Admin), Err(LoginError::UnknownUser(_))));
+        assert!("Moderator".parse::<Role>().is_ok_and(|r| r == Role::Moderator));
+        assert!("wizard".parse::<Role>().is_err());
+    }
+
+    #[test]
+    fn test_users_without_roles_are_players() {
+        let user: User = serde_json::from_str(r#"{"username": "old", "password": "ABC"}"#).unwrap();
+        assert_eq!(user.roles(), &BTreeSet::from([Role::Player]));
+    }
+
+    fn exercise_store(store: &mut dyn UserStore) {
+        store.insert(User::new("alice", "one").unwrap()).unwrap();
+        store.insert(User::new("bob", "two").unwrap()).unwrap();
+        assert_eq!(store.load().unwrap().len(), 2);
+        assert!(store.get("alice").unwrap().unwrap().verify_password("one"));
+        assert!(store.get("carol").unwrap().is_none());
+
+        store.update(User::new("alice", "three").unwrap()).unwrap();
+        assert!(store.get("alice").unwrap().unwrap().verify_password("three"));
+
+        assert!(matches!(store.insert(User::new("alice", "again").unwrap()), Err(LoginError::DuplicateUser(_))));
+        assert!(matches!(store.update(User::new("carol", "x").unwrap()), Err(LoginError::UnknownUser(_))));
+        assert!(matches!(store.delete("carol"), Err(LoginError::UnknownUser(_))));
+
+        store.delete("bob").unwrap();
+        let names: Vec<String> = store.load().unwrap().into_iter().map(|u| u.username).collect();
+        assert_eq!(names, vec!["alice".to_string()]);
+
+        // A batch is written whole or not at all
+        let batch = || (vec![User::new("dave", "four").unwrap()], vec![User::new("alice", "five").unwrap()]);
+        let (new, mut existing) = batch();
+        existing.push(User::new("carol", "x").unwrap());
+        assert!(matches!(store.write_batch(new, existing), Err(LoginError::UnknownUser(_))));
+        assert!(store.get("dave").unwrap().is_none());
+        assert!(store.get("alice").unwrap().unwrap().verify_password("three"));
+        let (new, existing) = batch();
+        store.write_batch(new, existing).unwrap();
+        assert!(store.get("dave").unwrap().is_some());
+        assert!(store.get("alice").unwrap().unwrap().verify_password("five"));
+    }
+
+    #[test]
+    fn test_memory_store() {
+        exercise_store(&mut MemoryStore::new());
+    }
+
+    #[test]
+    fn test_json_file_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("users.json");
+        exercise_store(&mut JsonFileStore::new(&path));
+        // A fresh store on the same file sees the same data
+        let mut reopened = JsonFileStore::new(&path);
+        assert!(reopened.get("alice").unwrap().unwrap().verify_password("five"));
+    }
+
+    #[test]
+    fn test_json_file_store_sees_external_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("users.json");
+        let mut server = JsonFileStore::new(&path);
+        let mut admin = JsonFileStore::new(&path);
+        assert!(server.get("alice").unwrap().is_none());
+        assert!(!server.has_changed().unwrap());
+
+        admin.insert(User::new("alice", "one").unwrap()).unwrap();
+        assert!(server.has_changed().unwrap());
+        assert!(server.get("alice").unwrap().is_some());
+        assert!(!server.has_changed().unwrap());
+
+        // Only the data file and its lock file remain - no stray temporary files
+        let mut files: Vec<String> = std::fs::read_dir(dir.path()).unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        files.sort();
+        assert_eq!(files, vec!["users.json", "users.json.lock"]);
+    }
+
+    #[test]
+    fn test_json_file_store_sees_same_size_rewrites() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("users.json");
+        let mut server = JsonFileStore::new(&path);
+        server.insert(User::with_params("alice", "one", &FAST).unwrap()).unwrap();
+        let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+
+        // Someone else renames alice in place, keeping the size and timestamp
+        let data = std::fs::read_to_string(&path).unwrap().replace("alice", "alick");
+        std::fs::write(&path, data).unwrap();
+        std::fs::File::options().write(true).open(&path).unwrap().set_modified(modified).unwrap();
+
+        assert!(server.has_changed().unwrap());
+        assert!(server.get("alice").unwrap().is_none());
+        assert!(server.get("alick").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_json_file_store_concurrent_writers() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("users.json");
+        let threads: Vec<_> = (0..8).map(|i| {
+            let path = path.clone();
+            std::thread::spawn(move || {
+                let mut store = JsonFileStore::new(path);
+                store.insert(User::with_params(&format!("user{i}"), "pw", &FAST).unwrap()).unwrap();
+            })
+        }).collect();
+        threads.into_iter().for_each(|t| t.join().unwrap());
+        assert_eq!(JsonFileStore::new(&path).load().unwrap().len(), 8);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_sqlite_store() {
+        exercise_store(&mut SqliteStore::open_in_memory().unwrap());
+    }
+
+    #[test]
+    fn test_store_config_parsing() {
+        use std::path::PathBuf;
+        assert_eq!("memory".parse::<StoreConfig>().unwrap(), StoreConfig::Memory);
+        assert_eq!("json:/tmp/u.json".parse::<StoreConfig>().unwrap(), StoreConfig::Json(PathBuf::from("/tmp/u.json")));
+        assert_eq!("users.json".parse::<StoreConfig>().unwrap(), StoreConfig::Json(PathBuf::from("users.json")));
+        #[cfg(feature = "sqlite")]
+        assert_eq!("users.db".parse::<StoreConfig>().unwrap(), StoreConfig::Sqlite(PathBuf::from("users.db")));
+    }
+}
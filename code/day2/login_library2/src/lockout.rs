@@ -0,0 +1,548 @@
+use std::{collections::HashMap, net::IpAddr, path::{Path, PathBuf}};
+use serde::{Deserialize, Serialize};
+use file_util::write_file_atomic;
+use crate::LoginError;
+
+/// How quickly repeated login failures are slowed down and then locked out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LockoutPolicy {
+    /// Failures allowed before any delay applies.
+    pub free_attempts: u32,
+    /// Delay after the first failure past `free_attempts`. Doubles with each further failure.
+    pub base_delay_secs: u64,
+    pub max_delay_secs: u64,
+    /// Failures after which logins are refused for `lockout_secs`.
+    pub lockout_after: u32,
+    pub lockout_secs: u64,
+    /// Failures are forgotten once this long has passed since the last one.
+    pub reset_after_secs: u64,
+}
+
+impl LockoutPolicy {
+    /// Defaults for a single account.
+    pub fn per_user() -> Self {
+        Self {
+            free_attempts: 3,
+            base_delay_secs: 2,
+            max_delay_secs: 60,
+            lockout_after: 10,
+            lockout_secs: 15 * 60,
+            reset_after_secs: 60 * 60,
+        }
+    }
+
+    /// Defaults for a source address, which may be shared by a whole classroom.
+    pub fn per_address() -> Self {
+        Self {
+            free_attempts: 10,
+            base_delay_secs: 1,
+            max_delay_secs: 30,
+            lockout_after: 50,
+            lockout_secs: 15 * 60,
+            reset_after_secs: 60 * 60,
+        }
+    }
+
+    /// Defaults for accounts created from one source address. Here every
+    /// registration counts, not just failures: enough for a classroom behind
+    /// one address, but not for one machine creating accounts without end.
+    pub fn registrations() -> Self {
+        Self {
+            free_attempts: 30,
+            base_delay_secs: 60,
+            max_delay_secs: 60 * 60,
+            lockout_after: 100,
+            lockout_secs: 24 * 60 * 60,
+            reset_after_secs: 24 * 60 * 60,
+        }
+    }
+}
+
+/// Why a login attempt was refused before the password was even checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoginBlock {
+    /// Too many recent failures; the caller must wait before trying again.
+    Throttled { retry_after_secs: u64 },
+    /// Too many failures in total; locked out for a longer period.
+    LockedOut { retry_after_secs: u64 },
+}
+
+impl LoginBlock {
+    pub fn retry_after_secs(&self) -> u64 {
+        match self {
+            LoginBlock::Throttled { retry_after_secs } | LoginBlock::LockedOut { retry_after_secs } => *retry_after_secs,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct FailureRecord {
+    failures: u32,
+    last_failure: u64,
+    locked_until: Option<u64>,
+}
+
+impl FailureRecord {
+    fn check(&self, policy: &LockoutPolicy, now: u64) -> Result<(), LoginBlock> {
+        if let Some(until) = self.locked_until
+            && until > now
+        {
+            return Err(LoginBlock::LockedOut { retry_after_secs: until - now });
+        }
+        if self.is_stale(policy, now) || self.failures <= policy.free_attempts {
+            return Ok(());
+        }
+        let doublings = self.failures - policy.free_attempts - 1;
+        let delay = 1u64
+            .checked_shl(doublings)
+            .map_or(u64::MAX, |factor| policy.base_delay_secs.saturating_mul(factor))
+            .min(policy.max_delay_secs);
+        let allowed_at = self.last_failure.saturating_add(delay);
+        if allowed_at > now {
+            return Err(LoginBlock::Throttled { retry_after_secs: allowed_at - now });
+        }
+        Ok(())
+    }
+
+    /// This record as it will be if `pending` attempts still in progress all fail now.
+    fn with_pending(&self, policy: &LockoutPolicy, pending: u32, now: u64) -> Self {
+        if pending == 0 {
+            return self.clone();
+        }
+        let mut record = if self.is_stale(policy, now) { Self::default() } else { self.clone() };
+        record.failures += pending;
+        record.last_failure = now;
+        record
+    }
+
+    fn is_stale(&self, policy: &LockoutPolicy, now: u64) -> bool {
+        self.locked_until.is_none_or(|until| until <= now)
+            && now.saturating_sub(self.last_failure) >= policy.reset_after_secs
+    }
+
+    /// Returns true if this failure started a lockout.
+    fn record_failure(&mut self, policy: &LockoutPolicy, now: u64) -> bool {
+        if self.is_stale(policy, now) {
+            *self = Self::default();
+        }
+        self.failures += 1;
+        self.last_failure = now;
+        // Once past the threshold, every further failure re-locks the key
+        if self.failures >= policy.lockout_after {
+            self.locked_until = Some(now + policy.lockout_secs);
+            return true;
+        }
+        false
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct FailureLog {
+    users: HashMap<String, FailureRecord>,
+    addresses: HashMap<String, FailureRecord>,
+    /// Accounts created per address, counted like failures
+    #[serde(default)]
+    registrations: HashMap<String, FailureRecord>,
+}
+
+/// How long an attempt let through by [`LoginThrottle::begin`] counts against
+/// its username and address if it's never finished, e.g. the caller failed.
+const PENDING_EXPIRY_SECS: u64 = 5 * 60;
+
+/// A login or registration attempt that [`LoginThrottle::begin`] or
+/// [`LoginThrottle::begin_registration`] let through. Until it's finished it
+/// counts as a failure (and as a registration), so attempts made in parallel
+/// can't all get past the check before any of them is recorded.
+#[derive(Debug)]
+#[must_use = "finish the attempt with `succeeded`, `failed` or `abandon`"]
+pub struct Attempt {
+    id: u64,
+    username: String,
+    addr: IpAddr,
+}
+
+#[derive(Debug)]
+struct Pending {
+    username: String,
+    address: String,
+    registration: bool,
+    started: u64,
+}
+
+/// Tracks failed logins per username and per source address, applying
+/// exponential back-off and temporary lockouts. Registrations per address
+/// are limited the same way, under [`LockoutPolicy::registrations`].
+///
+/// When opened with a path, the failure log can be saved so lockouts
+/// survive a server restart. Changes aren't written straight away: callers
+/// take an [`unsaved`](Self::unsaved) snapshot and write it when it suits
+/// them, e.g. off an async runtime, or call [`save`](Self::save).
+pub struct LoginThrottle {
+    user_policy: LockoutPolicy,
+    address_policy: LockoutPolicy,
+    registration_policy: LockoutPolicy,
+    log: FailureLog,
+    path: Option<PathBuf>,
+    /// Attempts let through and not yet finished, by id
+    pending: HashMap<u64, Pending>,
+    next_attempt: u64,
+    /// The log has changed since it was last saved
+    dirty: bool,
+}
+
+/// The throttle's failure log as of [`LoginThrottle::unsaved`], ready to write.
+#[derive(Debug)]
+pub struct ThrottleSnapshot {
+    path: PathBuf,
+    data: String,
+}
+
+impl ThrottleSnapshot {
+    /// Writes the snapshot over the throttle's file.
+    pub fn write(self) -> Result<(), LoginError> {
+        Ok(write_file_atomic(&self.path, self.data.as_bytes())?)
+    }
+}
+
+impl Default for LoginThrottle {
+    fn default() -> Self {
+        Self::new(LockoutPolicy::per_user(), LockoutPolicy::per_address())
+    }
+}
+
+impl LoginThrottle {
+    /// A throttle that only lives in memory.
+    pub fn new(user_policy: LockoutPolicy, address_policy: LockoutPolicy) -> Self {
+        Self::with_log(user_policy, address_policy, FailureLog::default(), None)
+    }
+
+    fn with_log(user_policy: LockoutPolicy, address_policy: LockoutPolicy, log: FailureLog, path: Option<PathBuf>) -> Self {
+        Self {
+            user_policy,
+            address_policy,
+            registration_policy: LockoutPolicy::registrations(),
+            log,
+            path,
+            pending: HashMap::new(),
+            next_attempt: 0,
+            dirty: false,
+        }
+    }
+
+    /// A throttle persisted to a JSON file, loading any existing failures.
+    pub fn open(path: impl AsRef<Path>, user_policy: LockoutPolicy, address_policy: LockoutPolicy) -> Result<Self, LoginError> {
+        let path = path.as_ref();
+        let log = if path.exists() {
+            serde_json::from_str(&std::fs::read_to_string(path)?)?
+        } else {
+            FailureLog::default()
+        };
+        Ok(Self::with_log(user_policy, address_policy, log, Some(path.to_path_buf())))
+    }
+
+    pub fn set_registration_policy(&mut self, policy: LockoutPolicy) {
+        self.registration_policy = policy;
+    }
+
+    /// How many attempts in progress match `counts`.
+    fn pending(&self, now: u64, counts: impl Fn(&Pending) -> bool) -> u32 {
+        let live = self.pending.values().filter(|p| now.saturating_sub(p.started) < PENDING_EXPIRY_SECS);
+        live.filter(|p| counts(p)).count() as u32
+    }
+
+    fn check_address(&self, address: &str, now: u64) -> Result<(), LoginBlock> {
+        let pending = self.pending(now, |p| p.address == address);
+        self.log.addresses.get(address).cloned().unwrap_or_default()
+            .with_pending(&self.address_policy, pending, now)
+            .check(&self.address_policy, now)
+    }
+
+    fn start(&mut self, username: &str, addr: IpAddr, registration: bool, now: u64) -> Attempt {
+        self.pending.retain(|_, p| now.saturating_sub(p.started) < PENDING_EXPIRY_SECS);
+        let id = self.next_attempt;
+        self.next_attempt += 1;
+        let pending = Pending { username: username.to_string(), address: addr.to_string(), registration, started: now };
+        self.pending.insert(id, pending);
+        Attempt { id, username: username.to_string(), addr }
+    }
+
+    /// Checks whether a login attempt may proceed, counting attempts still in
+    /// progress as failures. If both the username and the address are
+    /// blocked, the lockout (or the longer wait) wins.
+    pub fn check(&self, username: &str, addr: IpAddr, now: u64) -> Result<(), LoginBlock> {
+        let pending = self.pending(now, |p| p.username == username);
+        let user = self.log.users.get(username).cloned().unwrap_or_default()
+            .with_pending(&self.user_policy, pending, now)
+            .check(&self.user_policy, now);
+        let address = self.check_address(&addr.to_string(), now);
+        match (user, address) {
+            (Ok(()), Ok(())) => Ok(()),
+            (Err(block), Ok(())) | (Ok(()), Err(block)) => Err(block),
+            (Err(a), Err(b)) => Err(match (a, b) {
+                (LoginBlock::LockedOut { .. }, LoginBlock::Throttled { .. }) => a,
+                (LoginBlock::Throttled { .. }, LoginBlock::LockedOut { .. }) => b,
+                _ if a.retry_after_secs() >= b.retry_after_secs() => a,
+                _ => b,
+            }),
+        }
+    }
+
+    /// Checks a login attempt and, if it may proceed, counts it as in
+    /// progress until it's finished with [`succeeded`](Self::succeeded) or
+    /// [`failed`](Self::failed). An attempt never finished stops counting
+    /// after a few minutes.
+    pub fn begin(&mut self, username: &str, addr: IpAddr, now: u64) -> Result<Attempt, LoginBlock> {
+        self.check(username, addr, now)?;
+        Ok(self.start(username, addr, false, now))
+    }
+
+    /// Like [`begin`](Self::begin), for creating the account `username`, so
+    /// [`check_registration`](Self::check_registration) must pass too. Finish
+    /// it with [`registered`](Self::registered) if the account was created.
+    pub fn begin_registration(&mut self, username: &str, addr: IpAddr, now: u64) -> Result<Attempt, LoginBlock> {
+        self.check(username, addr, now)?;
+        self.check_registration(addr, now)?;
+        Ok(self.start(username, addr, true, now))
+    }
+
+    /// Finishes a registration that created the account; see
+    /// [`record_registration`](Self::record_registration).
+    pub fn registered(&mut self, attempt: Attempt, now: u64) -> bool {
+        self.pending.remove(&attempt.id);
+        self.record_registration(attempt.addr, now)
+    }
+
+    /// Finishes an attempt that failed, recording the failure. Returns true if
+    /// it locked out the username or the address.
+    pub fn failed(&mut self, attempt: Attempt, now: u64) -> bool {
+        self.pending.remove(&attempt.id);
+        self.record_failure(&attempt.username, attempt.addr, now)
+    }
+
+    /// Finishes an attempt that succeeded; see [`record_success`](Self::record_success).
+    pub fn succeeded(&mut self, attempt: Attempt) {
+        self.pending.remove(&attempt.id);
+        self.record_success(&attempt.username);
+    }
+
+    /// Finishes an attempt that neither succeeded nor failed, e.g. because the
+    /// user store couldn't be read, without recording anything.
+    pub fn abandon(&mut self, attempt: Attempt) {
+        self.pending.remove(&attempt.id);
+    }
+
+    /// Checks whether `addr` may create another account: it mustn't be
+    /// blocked from logging in, or have created too many accounts lately.
+    /// Registrations in progress count as made.
+    pub fn check_registration(&self, addr: IpAddr, now: u64) -> Result<(), LoginBlock> {
+        let address = addr.to_string();
+        self.check_address(&address, now)?;
+        let pending = self.pending(now, |p| p.registration && p.address == address);
+        self.log.registrations.get(&address).cloned().unwrap_or_default()
+            .with_pending(&self.registration_policy, pending, now)
+            .check(&self.registration_policy, now)
+    }
+
+    /// Counts an account created from `addr`. Returns true if the address
+    /// is now locked out of creating more.
+    pub fn record_registration(&mut self, addr: IpAddr, now: u64) -> bool {
+        self.dirty = true;
+        self.log.registrations
+            .entry(addr.to_string())
+            .or_default()
+            .record_failure(&self.registration_policy, now)
+    }
+
+    /// Records a failed attempt. Returns true if it locked out the username
+    /// or the address.
+    pub fn record_failure(&mut self, username: &str, addr: IpAddr, now: u64) -> bool {
+        self.dirty = true;
+        let user_locked = self.log.users
+            .entry(username.to_string())
+            .or_default()
+            .record_failure(&self.user_policy, now);
+        let address_locked = self.log.addresses
+            .entry(addr.to_string())
+            .or_default()
+            .record_failure(&self.address_policy, now);
+        user_locked || address_locked
+    }
+
+    /// Clears the failures recorded against a username after a good login.
+    /// Address failures are left to expire, so one valid account can't be
+    /// used to reset the counter for guesses against others.
+    pub fn record_success(&mut self, username: &str) {
+        if self.log.users.remove(username).is_some() {
+            self.dirty = true;
+        }
+    }
+
+    /// The failure log to write, if it has a file and has changed since the
+    /// last snapshot. Taking one counts as saving, so if writing it fails the
+    /// changes are only written again along with the next ones.
+    pub fn unsaved(&mut self, now: u64) -> Result<Option<ThrottleSnapshot>, LoginError> {
+        let Some(path) = &self.path else {
+            return Ok(None);
+        };
+        if !self.dirty {
+            return Ok(None);
+        }
+        let (user_policy, address_policy, registration_policy) = (self.user_policy, self.address_policy, self.registration_policy);
+        self.log.users.retain(|_, r| !r.is_stale(&user_policy, now));
+        self.log.addresses.retain(|_, r| !r.is_stale(&address_policy, now));
+        self.log.registrations.retain(|_, r| !r.is_stale(&registration_policy, now));
+        let data = serde_json::to_string_pretty(&self.log)?;
+        self.dirty = false;
+        Ok(Some(ThrottleSnapshot { path: path.clone(), data }))
+    }
+
+    /// Writes any unsaved changes now.
+    pub fn save(&mut self, now: u64) -> Result<(), LoginError> {
+        match self.unsaved(now)? {
+            Some(snapshot) => snapshot.write(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDR: IpAddr = IpAddr::V4(std::net::Ipv4Addr::LOCALHOST);
+
+    #[test]
+    fn test_free_attempts_then_backoff() {
+        let mut throttle = LoginThrottle::default();
+        for _ in 0..3 {
+            assert!(throttle.check("alice", ADDR, 100).is_ok());
+            throttle.record_failure("alice", ADDR, 100);
+        }
+        assert!(throttle.check("alice", ADDR, 100).is_ok());
+        throttle.record_failure("alice", ADDR, 100);
+        assert_eq!(throttle.check("alice", ADDR, 100), Err(LoginBlock::Throttled { retry_after_secs: 2 }));
+        assert!(throttle.check("alice", ADDR, 102).is_ok());
+        throttle.record_failure("alice", ADDR, 102);
+        assert_eq!(throttle.check("alice", ADDR, 102), Err(LoginBlock::Throttled { retry_after_secs: 4 }));
+        // Other users from the same address are unaffected
+        assert!(throttle.check("bob", ADDR, 102).is_ok());
+    }
+
+    #[test]
+    fn test_lockout_and_expiry() {
+        let mut throttle = LoginThrottle::default();
+        let mut now = 0;
+        let mut locked = false;
+        for _ in 0..10 {
+            now += 100;
+            locked = throttle.record_failure("alice", ADDR, now);
+        }
+        assert!(locked);
+        assert_eq!(throttle.check("alice", ADDR, now), Err(LoginBlock::LockedOut { retry_after_secs: 15 * 60 }));
+        assert!(throttle.check("alice", ADDR, now + 15 * 60).is_ok());
+        // Forgotten entirely after a quiet hour
+        assert!(!throttle.record_failure("alice", ADDR, now + 15 * 60 + 60 * 60));
+    }
+
+    #[test]
+    fn test_success_clears_user_failures() {
+        let mut throttle = LoginThrottle::default();
+        for _ in 0..5 {
+            throttle.record_failure("alice", ADDR, 100);
+        }
+        assert!(throttle.check("alice", ADDR, 100).is_err());
+        throttle.record_success("alice");
+        assert!(throttle.check("alice", ADDR, 100).is_ok());
+    }
+
+    #[test]
+    fn test_address_lockout_covers_all_users() {
+        let mut throttle = LoginThrottle::default();
+        for i in 0..50 {
+            throttle.record_failure(&format!("user{i}"), ADDR, 100);
+        }
+        assert!(matches!(throttle.check("someone_else", ADDR, 100), Err(LoginBlock::LockedOut { .. })));
+    }
+
+    #[test]
+    fn test_registrations_per_address() {
+        let mut throttle = LoginThrottle::default();
+        let policy = LockoutPolicy { free_attempts: 2, base_delay_secs: 60, ..LockoutPolicy::registrations() };
+        throttle.set_registration_policy(policy);
+        for _ in 0..2 {
+            assert!(throttle.check_registration(ADDR, 100).is_ok());
+            throttle.record_registration(ADDR, 100);
+        }
+        assert!(throttle.check_registration(ADDR, 100).is_ok());
+        throttle.record_registration(ADDR, 100);
+        assert_eq!(throttle.check_registration(ADDR, 100), Err(LoginBlock::Throttled { retry_after_secs: 60 }));
+        // Logging in isn't affected, but an address locked out of logging in can't register
+        assert!(throttle.check("alice", ADDR, 100).is_ok());
+        let other = IpAddr::V4(std::net::Ipv4Addr::new(10, 0, 0, 1));
+        for i in 0..50 {
+            throttle.record_failure(&format!("user{i}"), other, 100);
+        }
+        assert!(matches!(throttle.check_registration(other, 100), Err(LoginBlock::LockedOut { .. })));
+    }
+
+    #[test]
+    fn test_persisted_across_restarts() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("attempts.json");
+        let mut throttle = LoginThrottle::open(&path, LockoutPolicy::per_user(), LockoutPolicy::per_address()).unwrap();
+        for _ in 0..10 {
+            throttle.record_failure("alice", ADDR, 100);
+        }
+        // Nothing is written until it's saved
+        assert!(!path.exists());
+        let snapshot = throttle.unsaved(100).unwrap().unwrap();
+        assert!(throttle.unsaved(100).unwrap().is_none());
+        snapshot.write().unwrap();
+        let reopened = LoginThrottle::open(&path, LockoutPolicy::per_user(), LockoutPolicy::per_address()).unwrap();
+        assert!(matches!(reopened.check("alice", ADDR, 100), Err(LoginBlock::LockedOut { .. })));
+    }
+
+    #[test]
+    fn test_attempts_in_progress_count_as_failures() {
+        let mut throttle = LoginThrottle::default();
+        // Four guesses at once get through, as four in a row would, but no more
+        let attempts: Vec<Attempt> = (0..4).map(|_| throttle.begin("alice", ADDR, 100).unwrap()).collect();
+        assert_eq!(throttle.begin("alice", ADDR, 100).unwrap_err(), LoginBlock::Throttled { retry_after_secs: 2 });
+
+        // Each releases its slot; only the failures are recorded
+        let mut attempts = attempts.into_iter();
+        throttle.succeeded(attempts.next().unwrap());
+        for attempt in attempts {
+            assert!(!throttle.failed(attempt, 100));
+        }
+        assert_eq!(throttle.log.users["alice"].failures, 3);
+        assert!(throttle.pending.is_empty());
+        assert!(throttle.check("alice", ADDR, 100).is_ok());
+
+        // Attempts abandoned without finishing stop counting eventually
+        let mut throttle = LoginThrottle::default();
+        for _ in 0..4 {
+            let _ = throttle.begin("bob", ADDR, 100).unwrap();
+        }
+        assert!(throttle.check("bob", ADDR, 100).is_err());
+        assert!(throttle.check("bob", ADDR, 100 + PENDING_EXPIRY_SECS).is_ok());
+    }
+
+    #[test]
+    fn test_registrations_in_progress_count() {
+        let mut throttle = LoginThrottle::default();
+        let policy = LockoutPolicy { free_attempts: 2, base_delay_secs: 60, ..LockoutPolicy::registrations() };
+        throttle.set_registration_policy(policy);
+        let attempts: Vec<Attempt> = (0..3).map(|i| throttle.begin_registration(&format!("user{i}"), ADDR, 100).unwrap()).collect();
+        assert_eq!(throttle.begin_registration("user3", ADDR, 100).unwrap_err(), LoginBlock::Throttled { retry_after_secs: 60 });
+
+        // A name that was taken is a failure, not a registration
+        let mut attempts = attempts.into_iter();
+        assert!(!throttle.failed(attempts.next().unwrap(), 100));
+        for attempt in attempts {
+            assert!(!throttle.registered(attempt, 100));
+        }
+        assert_eq!(throttle.log.registrations[&ADDR.to_string()].failures, 2);
+        assert!(throttle.begin_registration("user3", ADDR, 100).is_ok());
+    }
+}
@@ -0,0 +1,200 @@
+use std::{path::Path, sync::LazyLock};
+use serde::{Deserialize, Serialize};
+use crate::LoginError;
+
+/// Passwords that are too commonly used to be allowed, bundled with the crate.
+static COMMON_PASSWORDS: LazyLock<Vec<&'static str>> = LazyLock::new(|| {
+    include_str!("common_passwords.txt")
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect()
+});
+
+/// Rules for new passwords. Existing passwords are never re-checked, so
+/// tightening the policy only affects new accounts and password changes.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct PasswordPolicy {
+    /// Minimum length, in characters
+    pub min_length: usize,
+    pub max_length: usize,
+    pub require_lowercase: bool,
+    pub require_uppercase: bool,
+    pub require_digit: bool,
+    /// Anything that isn't a letter or digit
+    pub require_symbol: bool,
+    /// Reject passwords on the bundled list of common passwords
+    pub deny_common: bool,
+    /// Reject passwords that contain the username
+    pub deny_username: bool,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            min_length: 8,
+            max_length: 128,
+            require_lowercase: false,
+            require_uppercase: false,
+            require_digit: false,
+            require_symbol: false,
+            deny_common: true,
+            deny_username: true,
+        }
+    }
+}
+
+impl PasswordPolicy {
+    /// Checks a password, listing every rule it breaks in the error.
+    pub fn check(&self, username: &str, password: &str) -> Result<(), LoginError> {
+        let mut problems = Vec::new();
+        let length = password.chars().count();
+        if length < self.min_length {
+            problems.push(format!("must be at least {} characters", self.min_length));
+        }
+        if length > self.max_length {
+            problems.push(format!("must be at most {} characters", self.max_length));
+        }
+        let classes = [
+            (self.require_lowercase, char::is_lowercase as fn(char) -> bool, "a lowercase letter"),
+            (self.require_uppercase, char::is_uppercase, "an uppercase letter"),
+            (self.require_digit, |c: char| c.is_ascii_digit(), "a digit"),
+            (self.require_symbol, |c: char| !c.is_alphanumeric(), "a symbol"),
+        ];
+        for (required, matches, name) in classes {
+            if required && !password.chars().any(matches) {
+                problems.push(format!("must contain {name}"));
+            }
+        }
+        if self.deny_common && COMMON_PASSWORDS.iter().any(|common| common.eq_ignore_ascii_case(password)) {
+            problems.push("is too common".to_string());
+        }
+        if self.deny_username
+            && !username.is_empty()
+            && password.to_lowercase().contains(&username.to_lowercase())
+        {
+            problems.push("must not contain the username".to_string());
+        }
+        if problems.is_empty() {
+            return Ok(());
+        }
+        Err(LoginError::PasswordPolicyViolation(format!("password {}", problems.join(", "))))
+    }
+}
+
+/// Rules for usernames, which are shown to other players and used as keys
+/// in files and maps.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct UsernamePolicy {
+    pub min_length: usize,
+    pub max_length: usize,
+    /// Allowed besides ASCII letters and digits
+    pub extra_chars: String,
+}
+
+impl Default for UsernamePolicy {
+    fn default() -> Self {
+        Self { min_length: 3, max_length: 32, extra_chars: "_-".to_string() }
+    }
+}
+
+impl UsernamePolicy {
+    pub fn check(&self, username: &str) -> Result<(), LoginError> {
+        let length = username.chars().count();
+        if length < self.min_length || length > self.max_length {
+            return Err(LoginError::InvalidUsername(format!(
+                "must be between {} and {} characters",
+                self.min_length, self.max_length
+            )));
+        }
+        if let Some(bad) = username.chars().find(|c| !c.is_ascii_alphanumeric() && !self.extra_chars.contains(*c)) {
+            let allowed: Vec<String> = self.extra_chars.chars().map(|c| format!("'{c}'")).collect();
+            let allowed = if allowed.is_empty() {
+                "letters and digits".to_string()
+            } else {
+                format!("letters, digits and {}", allowed.join(" "))
+            };
+            return Err(LoginError::InvalidUsername(format!("{:?} is not allowed; use only {}", bad, allowed)));
+        }
+        Ok(())
+    }
+}
+
+/// Everything [`crate::LoginManager`] checks before creating an account or
+/// changing a password. Can be loaded from a JSON file, where any missing
+/// field takes its default.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct AccountPolicy {
+    pub username: UsernamePolicy,
+    pub password: PasswordPolicy,
+}
+
+impl AccountPolicy {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, LoginError> {
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn violation(result: Result<(), LoginError>) -> String {
+        match result {
+            Err(LoginError::PasswordPolicyViolation(reason) | LoginError::InvalidUsername(reason)) => reason,
+            other => panic!("expected a policy violation, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_default_password_policy() {
+        let policy = PasswordPolicy::default();
+        assert!(policy.check("alice", "correct horse battery").is_ok());
+        assert_eq!(violation(policy.check("alice", "")), "password must be at least 8 characters");
+        assert_eq!(violation(policy.check("alice", "Password1")), "password is too common");
+        assert_eq!(violation(policy.check("alice", "xxALICExx")), "password must not contain the username");
+        assert_eq!(
+            violation(policy.check("alice", "qwerty")),
+            "password must be at least 8 characters, is too common"
+        );
+    }
+
+    #[test]
+    fn test_character_classes() {
+        let policy = PasswordPolicy {
+            require_lowercase: true,
+            require_uppercase: true,
+            require_digit: true,
+            require_symbol: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            violation(policy.check("alice", "lowercase only")),
+            "password must contain an uppercase letter, must contain a digit"
+        );
+        assert_eq!(violation(policy.check("alice", "NoSymbols123")), "password must contain a symbol");
+        assert!(policy.check("alice", "Tr0ub4dor&3").is_ok());
+    }
+
+    #[test]
+    fn test_username_policy() {
+        let policy = UsernamePolicy::default();
+        assert!(policy.check("Good_Name-1").is_ok());
+        assert_eq!(violation(policy.check("ab")), "must be between 3 and 32 characters");
+        assert_eq!(violation(policy.check("has space")), "' ' is not allowed; use only letters, digits and '_' '-'");
+        assert_eq!(violation(policy.check("tab\there")), "'\\t' is not allowed; use only letters, digits and '_' '-'");
+        let strict = UsernamePolicy { extra_chars: String::new(), ..Default::default() };
+        assert_eq!(violation(strict.check("a_b")), "'_' is not allowed; use only letters and digits");
+    }
+
+    #[test]
+    fn test_partial_policy_file() {
+        let policy: AccountPolicy = serde_json::from_str(r#"{ "password": { "min_length": 12 } }"#).unwrap();
+        assert_eq!(policy.password.min_length, 12);
+        assert!(policy.password.deny_common);
+        assert_eq!(policy.username, UsernamePolicy::default());
+    }
+}
@@ -0,0 +1,45 @@
+use std::{collections::BTreeSet, fmt, str::FromStr};
+use serde::{Deserialize, Serialize};
+use crate::LoginError;
+
+/// What an account is allowed to do. Every account is at least a player.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Player,
+    Builder,
+    Moderator,
+    Admin,
+}
+
+impl Role {
+    pub const ALL: [Role; 4] = [Role::Player, Role::Builder, Role::Moderator, Role::Admin];
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Role::Player => "player",
+            Role::Builder => "builder",
+            Role::Moderator => "moderator",
+            Role::Admin => "admin",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for Role {
+    type Err = LoginError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Role::ALL
+            .into_iter()
+            .find(|role| role.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| LoginError::UnknownRole(s.to_string()))
+    }
+}
+
+/// Accounts created before roles existed are plain players.
+pub(crate) fn default_roles() -> BTreeSet<Role> {
+    BTreeSet::from([Role::Player])
+}
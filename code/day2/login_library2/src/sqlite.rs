@@ -0,0 +1,106 @@
+use std::{path::Path, time::Duration};
+use rusqlite::{params, Connection, OptionalExtension};
+use crate::{store::UserStore, LoginError, User};
+
+/// Users kept in an embedded SQLite database.
+///
+/// Each row holds the username and the user serialized as JSON, so new
+/// fields on [`User`] don't need a schema migration.
+pub struct SqliteStore {
+    conn: Connection,
+}
+
+impl SqliteStore {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, LoginError> {
+        Self::init(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self, LoginError> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self, LoginError> {
+        // Let the CLI and a running server wait for each other rather than failing
+        conn.busy_timeout(Duration::from_secs(5))?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS users (username TEXT PRIMARY KEY, data TEXT NOT NULL)",
+            [],
+        )?;
+        Ok(Self { conn })
+    }
+}
+
+impl UserStore for SqliteStore {
+    fn load(&mut self) -> Result<Vec<User>, LoginError> {
+        let mut stmt = self.conn.prepare("SELECT data FROM users ORDER BY rowid")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        let mut users = Vec::new();
+        for data in rows {
+            users.push(serde_json::from_str(&data?)?);
+        }
+        Ok(users)
+    }
+
+    fn get(&mut self, username: &str) -> Result<Option<User>, LoginError> {
+        let data: Option<String> = self.conn
+            .query_row("SELECT data FROM users WHERE username = ?1", params![username], |row| row.get(0))
+            .optional()?;
+        match data {
+            Some(data) => Ok(Some(serde_json::from_str(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn insert(&mut self, user: User) -> Result<(), LoginError> {
+        insert_row(&self.conn, user)
+    }
+
+    fn update(&mut self, user: User) -> Result<(), LoginError> {
+        update_row(&self.conn, user)
+    }
+
+    fn delete(&mut self, username: &str) -> Result<(), LoginError> {
+        let deleted = self.conn.execute("DELETE FROM users WHERE username = ?1", params![username])?;
+        if deleted == 0 {
+            return Err(LoginError::UnknownUser(username.to_string()));
+        }
+        Ok(())
+    }
+
+    fn write_batch(&mut self, new: Vec<User>, existing: Vec<User>) -> Result<(), LoginError> {
+        // Rolled back if dropped before the commit
+        let tx = self.conn.transaction()?;
+        for user in new {
+            insert_row(&tx, user)?;
+        }
+        for user in existing {
+            update_row(&tx, user)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+fn insert_row(conn: &Connection, user: User) -> Result<(), LoginError> {
+    let data = serde_json::to_string(&user)?;
+    let inserted = conn.execute(
+        "INSERT OR IGNORE INTO users (username, data) VALUES (?1, ?2)",
+        params![user.username, data],
+    )?;
+    if inserted == 0 {
+        return Err(LoginError::DuplicateUser(user.username));
+    }
+    Ok(())
+}
+
+fn update_row(conn: &Connection, user: User) -> Result<(), LoginError> {
+    let data = serde_json::to_string(&user)?;
+    let updated = conn.execute(
+        "UPDATE users SET data = ?2 WHERE username = ?1",
+        params![user.username, data],
+    )?;
+    if updated == 0 {
+        return Err(LoginError::UnknownUser(user.username));
+    }
+    Ok(())
+}
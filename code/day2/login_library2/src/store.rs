@@ -0,0 +1,294 @@
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+};
+use file_util::{lock_file, write_file_atomic};
+use sha2::{Digest, Sha256};
+use crate::{LoginError, User};
+
+/// Somewhere to keep user accounts.
+///
+/// Every mutating call is persisted immediately, so there is no separate
+/// "save" step for callers to forget.
+pub trait UserStore: Send {
+    /// Returns every user in the store.
+    fn load(&mut self) -> Result<Vec<User>, LoginError>;
+    fn get(&mut self, username: &str) -> Result<Option<User>, LoginError>;
+    /// Adds a new user, failing with [`LoginError::DuplicateUser`] if the
+    /// username is taken.
+    fn insert(&mut self, user: User) -> Result<(), LoginError>;
+    /// Replaces the stored user with the same username, failing with
+    /// [`LoginError::UnknownUser`] if there isn't one.
+    fn update(&mut self, user: User) -> Result<(), LoginError>;
+    fn delete(&mut self, username: &str) -> Result<(), LoginError>;
+    /// Adds the `new` users and replaces the `existing` ones all at once. If
+    /// any of them fails, as [`insert`](Self::insert) or
+    /// [`update`](Self::update) would, nothing is written.
+    fn write_batch(&mut self, new: Vec<User>, existing: Vec<User>) -> Result<(), LoginError>;
+}
+
+/// Users kept in a JSON array on disk (the original `users.json` format).
+///
+/// Writes go to a temporary file that is renamed over the original, so a
+/// crash never leaves a truncated file behind. Readers and writers take an
+/// advisory lock on a `.lock` file alongside the data, so `login_cli2` and a
+/// running server can share one file safely. The parsed contents are cached;
+/// the file is still read on every access, but only parsed again when its
+/// contents have changed. Timestamps and sizes aren't trusted for this, as a
+/// rewrite can keep both.
+pub struct JsonFileStore {
+    path: PathBuf,
+    cache: Option<Cached>,
+}
+
+struct Cached {
+    users: Vec<User>,
+    /// Of the file the users were read from or written to; None if there was no file
+    digest: Option<[u8; 32]>,
+}
+
+fn digest(data: &str) -> [u8; 32] {
+    Sha256::digest(data.as_bytes()).into()
+}
+
+impl JsonFileStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into(), cache: None }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// True if the file on disk differs from what we last read or wrote.
+    pub fn has_changed(&self) -> Result<bool, LoginError> {
+        let _lock = self.lock(false)?;
+        let digest = self.read_file()?.as_deref().map(digest);
+        Ok(self.cache.as_ref().is_none_or(|cached| cached.digest != digest))
+    }
+
+    /// Re-reads the file if it changed underneath us. Returns true if it did.
+    pub fn reload_if_changed(&mut self) -> Result<bool, LoginError> {
+        let _lock = self.lock(false)?;
+        let data = self.read_file()?;
+        if self.cache.as_ref().is_some_and(|cached| cached.digest == data.as_deref().map(digest)) {
+            return Ok(false);
+        }
+        self.parse(data)?;
+        Ok(true)
+    }
+
+    fn lock(&self, exclusive: bool) -> Result<File, LoginError> {
+        Ok(lock_file(&self.path, exclusive)?)
+    }
+
+    /// The file's contents, or None if it doesn't exist yet.
+    fn read_file(&self) -> Result<Option<String>, LoginError> {
+        match std::fs::read_to_string(&self.path) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Parses what [`read_file`](Self::read_file) returned and caches it.
+    fn parse(&mut self, data: Option<String>) -> Result<&[User], LoginError> {
+        let (users, digest) = match data {
+            Some(data) => (serde_json::from_str(&data)?, Some(digest(&data))),
+            None => (vec![], None),
+        };
+        Ok(&self.cache.insert(Cached { users, digest }).users)
+    }
+
+    fn users(&mut self) -> Result<&[User], LoginError> {
+        self.reload_if_changed()?;
+        Ok(self.cache.as_ref().map(|c| c.users.as_slice()).unwrap_or_default())
+    }
+
+    /// Read-modify-write under an exclusive lock, always starting from the
+    /// current contents of the file rather than the cache.
+    fn modify(&mut self, change: impl FnOnce(&mut Vec<User>) -> Result<(), LoginError>) -> Result<(), LoginError> {
+        let _lock = self.lock(true)?;
+        let data = self.read_file()?;
+        let mut users = self.parse(data)?.to_vec();
+        change(&mut users)?;
+        let data = serde_json::to_string_pretty(&users)?;
+        write_file_atomic(&self.path, data.as_bytes())?;
+        self.cache = Some(Cached { users, digest: Some(digest(&data)) });
+        Ok(())
+    }
+}
+
+impl UserStore for JsonFileStore {
+    fn load(&mut self) -> Result<Vec<User>, LoginError> {
+        Ok(self.users()?.to_vec())
+    }
+
+    fn get(&mut self, username: &str) -> Result<Option<User>, LoginError> {
+        Ok(self.users()?.iter().find(|u| u.username == username).cloned())
+    }
+
+    fn insert(&mut self, user: User) -> Result<(), LoginError> {
+        self.modify(|users| insert_user(users, user))
+    }
+
+    fn update(&mut self, user: User) -> Result<(), LoginError> {
+        self.modify(|users| update_user(users, user))
+    }
+
+    fn delete(&mut self, username: &str) -> Result<(), LoginError> {
+        self.modify(|users| delete_user(users, username))
+    }
+
+    fn write_batch(&mut self, new: Vec<User>, existing: Vec<User>) -> Result<(), LoginError> {
+        self.modify(|users| write_users(users, new, existing))
+    }
+}
+
+/// Users kept in memory only. Useful for tests and throwaway servers.
+#[derive(Default)]
+pub struct MemoryStore {
+    users: Vec<User>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_users(users: Vec<User>) -> Self {
+        Self { users }
+    }
+}
+
+impl UserStore for MemoryStore {
+    fn load(&mut self) -> Result<Vec<User>, LoginError> {
+        Ok(self.users.clone())
+    }
+
+    fn get(&mut self, username: &str) -> Result<Option<User>, LoginError> {
+        Ok(self.users.iter().find(|u| u.username == username).cloned())
+    }
+
+    fn insert(&mut self, user: User) -> Result<(), LoginError> {
+        insert_user(&mut self.users, user)
+    }
+
+    fn update(&mut self, user: User) -> Result<(), LoginError> {
+        update_user(&mut self.users, user)
+    }
+
+    fn delete(&mut self, username: &str) -> Result<(), LoginError> {
+        delete_user(&mut self.users, username)
+    }
+
+    fn write_batch(&mut self, new: Vec<User>, existing: Vec<User>) -> Result<(), LoginError> {
+        let mut users = self.users.clone();
+        write_users(&mut users, new, existing)?;
+        self.users = users;
+        Ok(())
+    }
+}
+
+// Shared by the stores that keep users in a Vec
+
+fn insert_user(users: &mut Vec<User>, user: User) -> Result<(), LoginError> {
+    if users.iter().any(|u| u.username == user.username) {
+        return Err(LoginError::DuplicateUser(user.username));
+    }
+    users.push(user);
+    Ok(())
+}
+
+fn update_user(users: &mut [User], user: User) -> Result<(), LoginError> {
+    let Some(existing) = users.iter_mut().find(|u| u.username == user.username) else {
+        return Err(LoginError::UnknownUser(user.username));
+    };
+    *existing = user;
+    Ok(())
+}
+
+fn write_users(users: &mut Vec<User>, new: Vec<User>, existing: Vec<User>) -> Result<(), LoginError> {
+    for user in new {
+        insert_user(users, user)?;
+    }
+    for user in existing {
+        update_user(users, user)?;
+    }
+    Ok(())
+}
+
+fn delete_user(users: &mut Vec<User>, username: &str) -> Result<(), LoginError> {
+    let before = users.len();
+    users.retain(|u| u.username != username);
+    if users.len() == before {
+        return Err(LoginError::UnknownUser(username.to_string()));
+    }
+    Ok(())
+}
+
+/// Which [`UserStore`] to open, parsed from a string such as
+/// `json:/srv/mud/users.json`, `sqlite:users.db` or `memory`.
+///
+/// A bare path picks SQLite for `.db`/`.sqlite` files and JSON otherwise.
+/// Which variants exist depends on the crate's features, so matches on it
+/// outside this crate need a wildcard arm.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum StoreConfig {
+    Json(PathBuf),
+    Memory,
+    #[cfg(feature = "sqlite")]
+    Sqlite(PathBuf),
+}
+
+impl Default for StoreConfig {
+    fn default() -> Self {
+        StoreConfig::Json(PathBuf::from("users.json"))
+    }
+}
+
+impl std::str::FromStr for StoreConfig {
+    type Err = LoginError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "memory" {
+            return Ok(StoreConfig::Memory);
+        }
+        if let Some(path) = s.strip_prefix("json:") {
+            return Ok(StoreConfig::Json(PathBuf::from(path)));
+        }
+        if let Some(path) = s.strip_prefix("sqlite:") {
+            return Self::sqlite(PathBuf::from(path));
+        }
+        let path = PathBuf::from(s);
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("db" | "sqlite" | "sqlite3") => Self::sqlite(path),
+            _ => Ok(StoreConfig::Json(path)),
+        }
+    }
+}
+
+impl StoreConfig {
+    #[cfg(feature = "sqlite")]
+    fn sqlite(path: PathBuf) -> Result<Self, LoginError> {
+        Ok(StoreConfig::Sqlite(path))
+    }
+
+    #[cfg(not(feature = "sqlite"))]
+    fn sqlite(path: PathBuf) -> Result<Self, LoginError> {
+        Err(LoginError::UnsupportedStore(format!(
+            "{} (login_library2 was built without the `sqlite` feature)",
+            path.display()
+        )))
+    }
+
+    pub fn open(&self) -> Result<Box<dyn UserStore>, LoginError> {
+        Ok(match self {
+            StoreConfig::Json(path) => Box::new(JsonFileStore::new(path)),
+            StoreConfig::Memory => Box::new(MemoryStore::new()),
+            #[cfg(feature = "sqlite")]
+            StoreConfig::Sqlite(path) => Box::new(crate::sqlite::SqliteStore::open(path)?),
+        })
+    }
+}
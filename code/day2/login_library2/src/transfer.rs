@@ -0,0 +1,336 @@
+use std::{collections::{BTreeSet, HashSet}, str::FromStr};
+use argon2::PasswordHash;
+use serde::{Deserialize, Serialize};
+use crate::{role, AccountPolicy, LastLogin, LoginError, LoginManager, Role, Totp, User};
+
+/// A user as exported by [`LoginManager::export_users`] and accepted by
+/// [`LoginManager::import_users`].
+///
+/// Exports carry `password_hash`, which is imported as-is so nobody has to
+/// reset their password. Hand-written imports (say, a new training cohort)
+/// can give a plain `password` instead, which is hashed on import.
+/// `totp_secret` is only filled in when the export asks for it; without it,
+/// new users have two-factor authentication turned off, and users it
+/// replaces keep whatever they had.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct UserRecord {
+    pub username: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password_hash: Option<String>,
+    /// Missing or empty means just [`Role::Player`]
+    #[serde(default)]
+    pub roles: BTreeSet<Role>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub totp_secret: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_login: Option<LastLogin>,
+}
+
+impl From<&User> for UserRecord {
+    fn from(user: &User) -> Self {
+        Self {
+            username: user.username.clone(),
+            password: None,
+            password_hash: Some(user.password.clone()),
+            roles: user.roles.clone(),
+            totp_secret: None,
+            last_login: user.last_login.clone(),
+        }
+    }
+}
+
+/// What to do when an imported username already exists.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Import nothing if there are any conflicts
+    #[default]
+    Abort,
+    /// Keep the existing user and ignore the imported one
+    Skip,
+    /// Replace the existing user with the imported one, keeping their 2FA
+    /// unless the import has a `totp_secret`
+    Overwrite,
+}
+
+impl FromStr for ConflictPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "abort" => Ok(ConflictPolicy::Abort),
+            "skip" => Ok(ConflictPolicy::Skip),
+            "overwrite" => Ok(ConflictPolicy::Overwrite),
+            _ => Err(format!("unknown conflict policy {s:?} (expected abort, skip or overwrite)")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictKind {
+    /// The store already has a user with this name
+    Exists,
+    /// The name appears more than once in the import
+    Repeated,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportConflict {
+    pub username: String,
+    pub kind: ConflictKind,
+}
+
+/// What an import did, or would do in a dry run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    pub added: Vec<String>,
+    pub replaced: Vec<String>,
+    pub skipped: Vec<String>,
+    pub conflicts: Vec<ImportConflict>,
+    /// False for dry runs, and for imports aborted because of conflicts
+    pub applied: bool,
+}
+
+fn is_valid_hash(hash: &str) -> bool {
+    let legacy = hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_digit() || (b'A'..=b'F').contains(&b));
+    legacy || PasswordHash::new(hash).is_ok()
+}
+
+/// Checks a record without hashing anything, so dry runs are quick.
+fn validate(record: &UserRecord, policy: &AccountPolicy) -> Result<(), String> {
+    policy.username.check(&record.username).map_err(|e| e.to_string())?;
+    match (&record.password, &record.password_hash) {
+        (Some(_), Some(_)) => return Err("has both password and password_hash".to_string()),
+        (None, None) => return Err("needs a password or password_hash".to_string()),
+        (Some(password), None) => policy.password.check(&record.username, password).map_err(|e| e.to_string())?,
+        (None, Some(hash)) if !is_valid_hash(hash) => return Err("password_hash is not a recognised hash".to_string()),
+        (None, Some(_)) => {}
+    }
+    if let Some(secret) = &record.totp_secret
+        && Totp::from_secret(secret).is_none()
+    {
+        return Err("totp_secret is not valid base32".to_string());
+    }
+    Ok(())
+}
+
+impl LoginManager {
+    /// Every user, with password hashes. 2FA secrets are plaintext, so they
+    /// are left out unless `include_totp` is set; either way, treat the
+    /// result as being as sensitive as the store itself.
+    pub fn export_users(&self, include_totp: bool) -> Result<Vec<UserRecord>, LoginError> {
+        Ok(self.users()?.iter().map(|user| UserRecord {
+            totp_secret: user.totp.as_ref().filter(|_| include_totp).map(|totp| totp.secret().to_string()),
+            ..UserRecord::from(user)
+        }).collect())
+    }
+
+    /// Adds users in bulk. Every record is validated before anything is
+    /// written; an invalid record fails the whole import with
+    /// [`LoginError::InvalidRecord`]. The users are then written in one
+    /// [`UserStore::write_batch`](crate::UserStore::write_batch), so an
+    /// import that fails partway writes nothing. With `dry_run`, nothing is
+    /// written and the report says what would have happened.
+    pub fn import_users(&self, records: Vec<UserRecord>, on_conflict: ConflictPolicy, dry_run: bool) -> Result<ImportReport, LoginError> {
+        for (i, record) in records.iter().enumerate() {
+            validate(record, &self.policy).map_err(|reason| {
+                LoginError::InvalidRecord(format!("record {} ({}): {}", i + 1, record.username, reason))
+            })?;
+        }
+
+        // Work out what happens to each record. With Overwrite, the last of
+        // any repeated names wins; otherwise the first does.
+        let mut report = ImportReport::default();
+        let mut seen = HashSet::new();
+        let mut plan = Vec::new();
+        let ordered: Box<dyn Iterator<Item = UserRecord>> = match on_conflict {
+            ConflictPolicy::Overwrite => Box::new(records.into_iter().rev()),
+            _ => Box::new(records.into_iter()),
+        };
+        for record in ordered {
+            if !seen.insert(record.username.clone()) {
+                report.conflicts.push(ImportConflict { username: record.username.clone(), kind: ConflictKind::Repeated });
+                report.skipped.push(record.username);
+                continue;
+            }
+            let exists = self.store().get(&record.username)?.is_some();
+            if exists {
+                report.conflicts.push(ImportConflict { username: record.username.clone(), kind: ConflictKind::Exists });
+                if on_conflict == ConflictPolicy::Skip {
+                    report.skipped.push(record.username);
+                    continue;
+                }
+            }
+            plan.push((record, exists));
+        }
+        if on_conflict == ConflictPolicy::Overwrite {
+            plan.reverse();
+            report.conflicts.reverse();
+            report.skipped.reverse();
+        }
+
+        for (record, exists) in &plan {
+            if *exists {
+                report.replaced.push(record.username.clone());
+            } else {
+                report.added.push(record.username.clone());
+            }
+        }
+        if dry_run || (on_conflict == ConflictPolicy::Abort && !report.conflicts.is_empty()) {
+            return Ok(report);
+        }
+
+        // Hash any plain passwords before taking the store
+        let users = plan
+            .into_iter()
+            .map(|(record, exists)| Ok((self.user_from_record(record)?, exists)))
+            .collect::<Result<Vec<_>, LoginError>>()?;
+        let mut store = self.store();
+        let (mut existing, mut new) = (Vec::new(), Vec::new());
+        for (user, exists) in users {
+            if exists {
+                existing.push(user);
+            } else {
+                new.push(user);
+            }
+        }
+        // Exports leave 2FA secrets out by default, and importing one back
+        // mustn't quietly turn off 2FA for everyone
+        for user in existing.iter_mut().filter(|user| user.totp.is_none()) {
+            user.totp = store.get(&user.username)?.and_then(|current| current.totp);
+        }
+        store.write_batch(new, existing)?;
+        report.applied = true;
+        Ok(report)
+    }
+
+    fn user_from_record(&self, record: UserRecord) -> Result<User, LoginError> {
+        let password = match (record.password, record.password_hash) {
+            (_, Some(hash)) => hash,
+            (Some(password), None) => crate::hash_password(&password, &self.hash_params)?,
+            (None, None) => return Err(LoginError::InvalidRecord(format!("{}: no password", record.username))),
+        };
+        let mut roles = record.roles;
+        roles.extend(role::default_roles());
+        Ok(User {
+            username: record.username,
+            password,
+            roles,
+            totp: record.totp_secret.as_deref().and_then(Totp::from_secret),
+            last_login: record.last_login,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HashParams, MemoryStore};
+
+    fn manager() -> LoginManager {
+        let mut manager = crate::tests::lenient_manager(MemoryStore::new());
+        manager.set_hash_params(HashParams { memory_kib: 256, iterations: 1, parallelism: 1 });
+        manager
+    }
+
+    fn plain(username: &str, password: &str) -> UserRecord {
+        UserRecord {
+            username: username.to_string(),
+            password: Some(password.to_string()),
+            password_hash: None,
+            roles: BTreeSet::new(),
+            totp_secret: None,
+            last_login: None,
+        }
+    }
+
+    #[test]
+    fn test_export_import_round_trip_keeps_hashes() {
+        let source = manager();
+        source.add_user("alice", "one").unwrap();
+        source.grant_role("alice", Role::Builder).unwrap();
+        source.enroll_totp("alice").unwrap();
+        source.record_login("alice", 1234, Some("127.0.0.1:5000".to_string())).unwrap();
+        assert_eq!(source.export_users(false).unwrap()[0].totp_secret, None);
+        let exported = source.export_users(true).unwrap();
+        assert!(exported[0].totp_secret.is_some());
+
+        let target = manager();
+        let report = target.import_users(exported.clone(), ConflictPolicy::Abort, false).unwrap();
+        assert!(report.applied);
+        assert_eq!(report.added, ["alice"]);
+        assert_eq!(target.export_users(true).unwrap(), exported);
+        assert!(target.verify_user("alice", "one").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_plain_passwords_are_hashed() {
+        let manager = manager();
+        manager.import_users(vec![plain("alice", "one"), plain("bob", "two")], ConflictPolicy::Abort, false).unwrap();
+        let exported = manager.export_users(false).unwrap();
+        assert!(exported.iter().all(|r| r.password_hash.as_ref().is_some_and(|h| h.starts_with("$argon2id$"))));
+        assert!(manager.verify_user("bob", "two").unwrap().unwrap().has_role(Role::Player));
+    }
+
+    #[test]
+    fn test_dry_run_reports_conflicts_without_writing() {
+        let manager = manager();
+        manager.add_user("alice", "one").unwrap();
+        let records = vec![plain("alice", "new"), plain("bob", "two"), plain("bob", "three")];
+
+        let report = manager.import_users(records.clone(), ConflictPolicy::Skip, true).unwrap();
+        assert!(!report.applied);
+        assert_eq!(report.added, ["bob"]);
+        assert_eq!(report.skipped, ["alice", "bob"]);
+        assert_eq!(report.conflicts, [
+            ImportConflict { username: "alice".to_string(), kind: ConflictKind::Exists },
+            ImportConflict { username: "bob".to_string(), kind: ConflictKind::Repeated },
+        ]);
+        assert_eq!(manager.users().unwrap().len(), 1);
+
+        // Abort writes nothing when there are conflicts
+        let report = manager.import_users(records.clone(), ConflictPolicy::Abort, false).unwrap();
+        assert!(!report.applied);
+        assert_eq!(manager.users().unwrap().len(), 1);
+
+        // Overwrite replaces alice, and the last bob wins
+        let report = manager.import_users(records, ConflictPolicy::Overwrite, false).unwrap();
+        assert!(report.applied);
+        assert_eq!(report.replaced, ["alice"]);
+        assert!(manager.verify_user("alice", "new").unwrap().is_some());
+        assert!(manager.verify_user("bob", "three").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_overwrite_keeps_2fa_unless_imported() {
+        let manager = manager();
+        manager.add_user("alice", "one").unwrap();
+        manager.add_user("bob", "two").unwrap();
+        let alice_totp = manager.enroll_totp("alice").unwrap();
+        manager.enroll_totp("bob").unwrap();
+        let exported = manager.export_users(false).unwrap();
+        assert!(exported.iter().all(|r| r.totp_secret.is_none()));
+
+        // The default export, imported back over the same users
+        let mut records = exported.clone();
+        let bob_totp = Totp::generate();
+        records[1].totp_secret = Some(bob_totp.secret().to_string());
+        manager.import_users(records, ConflictPolicy::Overwrite, false).unwrap();
+        let secrets: Vec<Option<String>> = manager.export_users(true).unwrap().into_iter().map(|r| r.totp_secret).collect();
+        assert_eq!(secrets, [Some(alice_totp.secret().to_string()), Some(bob_totp.secret().to_string())]);
+    }
+
+    #[test]
+    fn test_invalid_records_fail_the_whole_import() {
+        let manager = manager();
+        let mut bad_hash = plain("carol", "x");
+        bad_hash.password = None;
+        bad_hash.password_hash = Some("not a hash".to_string());
+        for bad in [plain("has space", "x"), plain("dave", ""), bad_hash] {
+            let records = vec![plain("alice", "one"), bad];
+            assert!(matches!(manager.import_users(records, ConflictPolicy::Abort, false), Err(LoginError::InvalidRecord(_))));
+        }
+        assert!(manager.users().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,87 @@
+use std::{collections::HashSet, path::PathBuf};
+use clap::Parser;
+use colored::Colorize;
+use rooms_library2::{Issue, RoomLibrary, WorldFormat};
+
+/// Converts a world between JSON, TOML, RON and YAML
+#[derive(Debug, Parser)]
+struct Args {
+    /// A rooms file, or a directory of zone files
+    input: PathBuf,
+
+    /// The file to write, in the format its extension names, or the
+    /// directory to write converted zone files into
+    output: PathBuf,
+
+    /// Format to convert a directory of zone files to: json, toml, ron or yaml
+    #[arg(long)]
+    to: Option<WorldFormat>,
+
+    /// Replace files that already exist
+    #[arg(long)]
+    force: bool,
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+
+    let mut world = RoomLibrary::check(&args.input)?;
+    if world.report.issues.iter().any(|issue| matches!(issue, Issue::DuplicateRoom { .. })) {
+        anyhow::bail!("Resolve the duplicate rooms first; converting would drop them\n{}", world.report);
+    }
+    let errors = world.report.errors().count();
+    if errors > 0 {
+        eprintln!("{}", format!("The world has {errors} error(s); run rooms_lint for details").yellow());
+    }
+
+    // Work out where each zone goes, keeping a directory's layout
+    let format = if args.input.is_dir() {
+        let Some(format) = args.to else {
+            anyhow::bail!("Use --to to choose a format when converting a directory");
+        };
+        for (path, _) in &mut world.zones {
+            let relative = path.strip_prefix(&args.input)?;
+            *path = args.output.join(relative).with_extension(format.extension());
+        }
+        format
+    } else {
+        let Some(format) = WorldFormat::from_path(&args.output) else {
+            anyhow::bail!("Can't tell the format of {}; use .json, .toml, .ron or .yaml", args.output.display());
+        };
+        if args.to.is_some_and(|to| to != format) {
+            anyhow::bail!("{} doesn't have a .{} extension", args.output.display(), args.to.unwrap());
+        }
+        world.zones[0].0 = args.output.clone();
+        format
+    };
+
+    let mut targets = HashSet::new();
+    for (path, _) in &world.zones {
+        if !targets.insert(path) {
+            anyhow::bail!("More than one zone would be written to {}", path.display());
+        }
+        if path.exists() && !args.force {
+            anyhow::bail!("{} already exists; use --force to replace it", path.display());
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+    }
+    world.save()?;
+
+    // Read back each file written, to be sure nothing was lost on the way.
+    // Just these files: anything else already in the output directory isn't ours.
+    for (path, names) in &world.zones {
+        let converted = RoomLibrary::check(path)?;
+        let matches = converted.rooms.len() == names.len()
+            && names.iter().all(|name| converted.rooms.get(name) == world.rooms.get(name));
+        if !matches {
+            anyhow::bail!("The converted zone {} doesn't match the original", path.display());
+        }
+    }
+    println!(
+        "{}",
+        format!("Converted {} rooms in {} file(s) to {}", world.rooms.len(), world.zones.len(), format).bright_green()
+    );
+    Ok(())
+}
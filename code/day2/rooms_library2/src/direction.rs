@@ -0,0 +1,146 @@
+use std::{fmt, str::FromStr};
+
+/// The standard directions, which players can type in full or abbreviated
+/// in any case. Exits can also use any other word, such as "portal".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Direction {
+    North,
+    Northeast,
+    East,
+    Southeast,
+    South,
+    Southwest,
+    West,
+    Northwest,
+    Up,
+    Down,
+    In,
+    Out,
+}
+
+impl Direction {
+    pub const ALL: [Direction; 12] = [
+        Direction::North,
+        Direction::Northeast,
+        Direction::East,
+        Direction::Southeast,
+        Direction::South,
+        Direction::Southwest,
+        Direction::West,
+        Direction::Northwest,
+        Direction::Up,
+        Direction::Down,
+        Direction::In,
+        Direction::Out,
+    ];
+
+    /// The canonical spelling, as used in room files.
+    pub fn name(self) -> &'static str {
+        match self {
+            Direction::North => "north",
+            Direction::Northeast => "northeast",
+            Direction::East => "east",
+            Direction::Southeast => "southeast",
+            Direction::South => "south",
+            Direction::Southwest => "southwest",
+            Direction::West => "west",
+            Direction::Northwest => "northwest",
+            Direction::Up => "up",
+            Direction::Down => "down",
+            Direction::In => "in",
+            Direction::Out => "out",
+        }
+    }
+
+    pub fn abbreviation(self) -> Option<&'static str> {
+        let abbreviation = match self {
+            Direction::North => "n",
+            Direction::Northeast => "ne",
+            Direction::East => "e",
+            Direction::Southeast => "se",
+            Direction::South => "s",
+            Direction::Southwest => "sw",
+            Direction::West => "w",
+            Direction::Northwest => "nw",
+            Direction::Up => "u",
+            Direction::Down => "d",
+            Direction::In | Direction::Out => return None,
+        };
+        Some(abbreviation)
+    }
+
+    /// The way back.
+    pub fn opposite(self) -> Direction {
+        match self {
+            Direction::North => Direction::South,
+            Direction::Northeast => Direction::Southwest,
+            Direction::East => Direction::West,
+            Direction::Southeast => Direction::Northwest,
+            Direction::South => Direction::North,
+            Direction::Southwest => Direction::Northeast,
+            Direction::West => Direction::East,
+            Direction::Northwest => Direction::Southeast,
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::In => Direction::Out,
+            Direction::Out => Direction::In,
+        }
+    }
+
+    /// Where this leads on a map with north at the top, for the compass
+    /// directions.
+    pub fn grid_offset(self) -> Option<(i32, i32)> {
+        let offset = match self {
+            Direction::North => (0, -1),
+            Direction::Northeast => (1, -1),
+            Direction::East => (1, 0),
+            Direction::Southeast => (1, 1),
+            Direction::South => (0, 1),
+            Direction::Southwest => (-1, 1),
+            Direction::West => (-1, 0),
+            Direction::Northwest => (-1, -1),
+            Direction::Up | Direction::Down | Direction::In | Direction::Out => return None,
+        };
+        Some(offset)
+    }
+
+    /// Reads a full name or abbreviation in any case, so `N`, `north` and
+    /// `North` are all [`Direction::North`].
+    pub fn parse(s: &str) -> Option<Direction> {
+        let s = s.trim();
+        Direction::ALL
+            .into_iter()
+            .find(|d| d.name().eq_ignore_ascii_case(s) || d.abbreviation().is_some_and(|a| a.eq_ignore_ascii_case(s)))
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Direction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Direction::parse(s).ok_or_else(|| format!("unknown direction {s:?}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        for input in ["n", "N", "north", "North", " NORTH "] {
+            assert_eq!(Direction::parse(input), Some(Direction::North), "{input}");
+        }
+        assert_eq!(Direction::parse("sw"), Some(Direction::Southwest));
+        assert_eq!(Direction::parse("u"), Some(Direction::Up));
+        assert_eq!(Direction::parse("In"), Some(Direction::In));
+        assert_eq!(Direction::parse("portal"), None);
+        assert!(Direction::ALL.iter().all(|d| d.opposite().opposite() == *d));
+    }
+}
@@ -0,0 +1,77 @@
+use std::{collections::HashMap, fmt};
+use serde::{ser::SerializeStruct, Deserialize, Serialize, Serializer};
+use crate::{optional_field, Exit, Room};
+
+/// A door on an exit. The exits on both sides of a doorway name the same
+/// door, and share its state once the world is running; see [`door_id`].
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Door {
+    /// Shown to players, e.g. "oak door". Doors in different doorways can
+    /// share a name.
+    pub name: String,
+    /// Ties the two sides together when they can't be matched up by name
+    /// alone, e.g. two "gate"s between the same pair of rooms
+    #[serde(default)]
+    pub id: Option<String>,
+    /// How the door starts out
+    #[serde(default)]
+    pub state: DoorState,
+    /// The item that locks and unlocks it. Doors without a key can't be locked.
+    #[serde(default)]
+    pub key: Option<String>,
+    /// Seconds after being opened that it swings shut by itself
+    #[serde(default)]
+    pub closes_after: Option<u64>,
+}
+
+impl Serialize for Door {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let terse = serializer.is_human_readable();
+        let mut door = serializer.serialize_struct("Door", 5)?;
+        door.serialize_field("name", &self.name)?;
+        optional_field(&mut door, terse, "id", &self.id, self.id.is_none())?;
+        door.serialize_field("state", &self.state)?;
+        optional_field(&mut door, terse, "key", &self.key, self.key.is_none())?;
+        optional_field(&mut door, terse, "closes_after", &self.closes_after, self.closes_after.is_none())?;
+        door.end()
+    }
+}
+
+/// What the door on `exit`, leading out of `room`, is known by while the
+/// world runs. That's its `id` if either side has one; otherwise it's
+/// `room/direction` for whichever side of the doorway sorts first, the other
+/// side being the exit back with a door of the same name. None if the exit
+/// has no door.
+pub fn door_id(rooms: &HashMap<String, Room>, room: &str, exit: &Exit) -> Option<String> {
+    let door = exit.door.as_ref()?;
+    let here = format!("{room}/{}", exit.direction.to_lowercase());
+    let back = rooms.get(&exit.room_name).and_then(|target| {
+        target.exits.iter().find(|back| back.room_name == room && back.door.as_ref().is_some_and(|d| d.name.eq_ignore_ascii_case(&door.name)))
+    });
+    if let Some(id) = door.id.as_ref().or_else(|| back?.door.as_ref()?.id.as_ref()) {
+        return Some(id.clone());
+    }
+    Some(match back {
+        Some(back) => here.min(format!("{}/{}", exit.room_name, back.direction.to_lowercase())),
+        None => here,
+    })
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum DoorState {
+    Open,
+    #[default]
+    Closed,
+    Locked,
+}
+
+impl fmt::Display for DoorState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DoorState::Open => "open",
+            DoorState::Closed => "closed",
+            DoorState::Locked => "locked",
+        })
+    }
+}
@@ -0,0 +1,94 @@
+use std::{collections::HashMap, fmt};
+use itertools::Itertools;
+use crate::{Direction, Exit, Room};
+
+/// A change made by [`fix`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fix {
+    /// A direction rewritten in full and in lowercase
+    NormalisedDirection { room: String, from: String, to: String },
+    /// An exit added so a one-way exit can be walked back
+    AddedExit { room: String, direction: String, target: String },
+}
+
+impl fmt::Display for Fix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Fix::NormalisedDirection { room, from, to } => write!(f, "renamed exit {from} in '{room}' to {to}"),
+            Fix::AddedExit { room, direction, target } => write!(f, "added exit {direction} from '{room}' to '{target}'"),
+        }
+    }
+}
+
+/// Fixes the problems that have only one sensible fix: standard directions
+/// are spelled out in full (so `N` becomes `north`), other directions are
+/// lowercased, and one-way exits in a standard direction get a reciprocal
+/// exit, with the same door if there is one, unless the target room already
+/// uses the opposite direction.
+pub fn fix(rooms: &mut HashMap<String, Room>) -> Vec<Fix> {
+    let mut fixes = Vec::new();
+    let names = rooms.keys().sorted().cloned().collect_vec();
+
+    for name in &names {
+        for exit in &mut rooms.get_mut(name).unwrap().exits {
+            let normalised = exit
+                .canonical_direction()
+                .map_or_else(|| exit.direction.to_lowercase(), |d| d.name().to_string());
+            if normalised != exit.direction {
+                let from = std::mem::replace(&mut exit.direction, normalised.clone());
+                fixes.push(Fix::NormalisedDirection { room: name.clone(), from, to: normalised });
+            }
+        }
+    }
+
+    for name in &names {
+        for exit in rooms[name].exits.clone() {
+            let Some(back) = exit.canonical_direction().map(Direction::opposite) else {
+                continue;
+            };
+            let Some(target) = rooms.get_mut(&exit.room_name) else {
+                continue;
+            };
+            let leads_back = target.exits.iter().any(|e| e.room_name == *name);
+            // An alias such as "down" on the trapdoor counts, since players can type it
+            let taken = target.exits.iter().flat_map(Exit::words).any(|word| Direction::parse(word) == Some(back));
+            if leads_back || taken {
+                continue;
+            }
+            target.exits.push(Exit { direction: back.to_string(), room_name: name.clone(), aliases: Vec::new(), door: exit.door });
+            fixes.push(Fix::AddedExit { room: exit.room_name.clone(), direction: back.to_string(), target: name.clone() });
+        }
+    }
+
+    fixes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{exit, world};
+
+    #[test]
+    fn test_fix() {
+        let mut rooms = world(&[
+            ("Hall", true, &[("N", "Study"), ("down", "Cellar"), ("Portal", "Attic")]),
+            ("Study", true, &[]),
+            ("Cellar", true, &[("up", "Attic")]),
+            ("Attic", true, &[]),
+            ("Vault", true, &[("east", "Cellar")]),
+        ]);
+        rooms.get_mut("Cellar").unwrap().exits.push(Exit { aliases: vec!["W".to_string()], ..exit("tunnel", "Attic") });
+        let s = str::to_string;
+        assert_eq!(fix(&mut rooms), [
+            Fix::NormalisedDirection { room: s("Hall"), from: s("N"), to: s("north") },
+            Fix::NormalisedDirection { room: s("Hall"), from: s("Portal"), to: s("portal") },
+            Fix::AddedExit { room: s("Attic"), direction: s("down"), target: s("Cellar") },
+            Fix::AddedExit { room: s("Study"), direction: s("south"), target: s("Hall") },
+        ]);
+        // "portal" has no opposite, the Cellar's "up" is already taken, and so
+        // is its west, by the tunnel's alias
+        assert!(rooms["Attic"].exits.iter().all(|e| e.room_name != "Hall"));
+        assert_eq!(rooms["Cellar"].exits.len(), 2);
+        assert!(fix(&mut rooms).is_empty());
+    }
+}
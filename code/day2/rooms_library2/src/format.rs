@@ -0,0 +1,105 @@
+use std::path::Path;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// A file format worlds can be written in, chosen by file extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorldFormat {
+    Json,
+    Toml,
+    Ron,
+    Yaml,
+}
+
+impl WorldFormat {
+    pub const ALL: [WorldFormat; 4] = [WorldFormat::Json, WorldFormat::Toml, WorldFormat::Ron, WorldFormat::Yaml];
+
+    pub fn from_path(path: &Path) -> Option<WorldFormat> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "json" => Some(WorldFormat::Json),
+            "toml" => Some(WorldFormat::Toml),
+            "ron" => Some(WorldFormat::Ron),
+            "yaml" | "yml" => Some(WorldFormat::Yaml),
+            _ => None,
+        }
+    }
+
+    /// Whether files in this format can hold comments, which saving loses.
+    pub fn has_comments(self) -> bool {
+        self != WorldFormat::Json
+    }
+
+    /// The extension new files in this format get.
+    pub fn extension(self) -> &'static str {
+        match self {
+            WorldFormat::Json => "json",
+            WorldFormat::Toml => "toml",
+            WorldFormat::Ron => "ron",
+            WorldFormat::Yaml => "yaml",
+        }
+    }
+
+    pub(crate) fn parse<T: DeserializeOwned>(self, data: &str) -> Result<T, FormatError> {
+        Ok(match self {
+            WorldFormat::Json => serde_json::from_str(data)?,
+            WorldFormat::Toml => toml::from_str(data).map_err(|e| FormatError::TomlRead(Box::new(e)))?,
+            WorldFormat::Ron => ron::from_str(data).map_err(|e| FormatError::RonRead(Box::new(e)))?,
+            WorldFormat::Yaml => serde_norway::from_str(data)?,
+        })
+    }
+
+    /// Pretty-printed, with the four-space indent the JSON rooms files use.
+    pub(crate) fn render(self, value: &impl Serialize) -> Result<String, FormatError> {
+        let mut text = match self {
+            WorldFormat::Json => {
+                let mut buf = Vec::new();
+                let formatter = serde_json::ser::PrettyFormatter::with_indent(b"    ");
+                value.serialize(&mut serde_json::Serializer::with_formatter(&mut buf, formatter))?;
+                String::from_utf8(buf).expect("serde_json writes UTF-8")
+            }
+            WorldFormat::Toml => toml::to_string_pretty(value)?,
+            WorldFormat::Ron => ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::new().indentor("    "))?,
+            WorldFormat::Yaml => serde_norway::to_string(value)?,
+        };
+        if !text.ends_with('\n') {
+            text.push('\n');
+        }
+        Ok(text)
+    }
+}
+
+impl std::fmt::Display for WorldFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.extension())
+    }
+}
+
+impl std::str::FromStr for WorldFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        WorldFormat::from_path(Path::new(&format!("x.{s}")))
+            .ok_or_else(|| format!("unknown world format {s:?} (expected json, toml, ron or yaml)"))
+    }
+}
+
+/// Why a world file couldn't be read or written. The parse errors include
+/// the line and column of the problem.
+#[derive(thiserror::Error, Debug)]
+pub enum FormatError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    // Boxed, as these are much larger than the others
+    #[error(transparent)]
+    TomlRead(Box<toml::de::Error>),
+    #[error(transparent)]
+    TomlWrite(#[from] toml::ser::Error),
+    #[error(transparent)]
+    RonRead(Box<ron::error::SpannedError>),
+    #[error(transparent)]
+    RonWrite(#[from] ron::Error),
+    #[error(transparent)]
+    Yaml(#[from] serde_norway::Error),
+}
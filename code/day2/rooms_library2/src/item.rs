@@ -0,0 +1,151 @@
+use std::{collections::BTreeMap, fmt};
+use serde::{de::Visitor, ser::SerializeStruct, Deserialize, Deserializer, Serialize, Serializer};
+use crate::optional_field;
+
+/// Something that can be picked up. Items are placed in rooms in the room
+/// files, and move between rooms and players once the world is running.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Item {
+    /// What players call it, e.g. "brass key"
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Anything else about the item, for gameplay to build on, e.g. `weight = 2`
+    #[serde(default)]
+    pub properties: BTreeMap<String, Property>,
+}
+
+impl Item {
+    /// Whether `input` names this item, in any case.
+    pub fn is_called(&self, input: &str) -> bool {
+        self.name.eq_ignore_ascii_case(input.trim())
+    }
+}
+
+impl Serialize for Item {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let terse = serializer.is_human_readable();
+        let mut item = serializer.serialize_struct("Item", 3)?;
+        item.serialize_field("name", &self.name)?;
+        optional_field(&mut item, terse, "description", &self.description, self.description.is_empty())?;
+        optional_field(&mut item, terse, "properties", &self.properties, self.properties.is_empty())?;
+        item.end()
+    }
+}
+
+/// The value of an item property.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Property {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Text(String),
+}
+
+impl Property {
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Property::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            Property::Int(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    /// Whole numbers count too.
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            Property::Int(i) => Some(*i as f64),
+            Property::Float(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Property::Text(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Property {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Property::Bool(b) => write!(f, "{b}"),
+            Property::Int(i) => write!(f, "{i}"),
+            Property::Float(x) => write!(f, "{x}"),
+            Property::Text(s) => f.write_str(s),
+        }
+    }
+}
+
+// Room files write properties as plain values. Bincode can't tell a value's
+// type by looking at it, so the network protocol keeps the variant.
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Property")]
+enum Tagged {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Text(String),
+}
+
+impl Serialize for Property {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if !serializer.is_human_readable() {
+            return Tagged::serialize(self, serializer);
+        }
+        match self {
+            Property::Bool(b) => serializer.serialize_bool(*b),
+            Property::Int(i) => serializer.serialize_i64(*i),
+            Property::Float(x) => serializer.serialize_f64(*x),
+            Property::Text(s) => serializer.serialize_str(s),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Property {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct PropertyVisitor;
+
+        impl Visitor<'_> for PropertyVisitor {
+            type Value = Property;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a boolean, number or string")
+            }
+
+            fn visit_bool<E: serde::de::Error>(self, v: bool) -> Result<Property, E> {
+                Ok(Property::Bool(v))
+            }
+
+            fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<Property, E> {
+                Ok(Property::Int(v))
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<Property, E> {
+                i64::try_from(v).map(Property::Int).map_err(E::custom)
+            }
+
+            fn visit_f64<E: serde::de::Error>(self, v: f64) -> Result<Property, E> {
+                Ok(Property::Float(v))
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Property, E> {
+                Ok(Property::Text(v.to_string()))
+            }
+        }
+
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(PropertyVisitor)
+        } else {
+            Tagged::deserialize(deserializer)
+        }
+    }
+}
@@ -1,18 +1,134 @@
-use std::collections::HashMap;
-use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt, path::{Path, PathBuf}};
+use serde::{de::{MapAccess, Visitor}, ser::SerializeStruct, Deserialize, Deserializer, Serialize, Serializer};
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+mod direction;
+mod door;
+mod fix;
+mod format;
+mod item;
+mod map;
+mod npc;
+mod players;
+#[cfg(test)]
+pub(crate) mod test_util;
+mod validate;
+pub use direction::Direction;
+pub use door::{door_id, Door, DoorState};
+pub use fix::{fix, Fix};
+pub use format::{FormatError, WorldFormat};
+pub use item::{Item, Property};
+pub use map::{to_ascii_map, to_dot};
+pub use npc::{rooms_within, Npc};
+pub use players::{JsonPlayerStore, MemoryPlayerStore, PlayerState, PlayerStore, PlayerStoreConfig, PlayerStoreError};
+pub use validate::{validate, Issue, Severity, ValidationReport};
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
 pub struct Room {
     pub name: String,
     pub description: String,
     pub exits: Vec<Exit>,
+    /// What's lying here when the world loads
+    #[serde(default)]
+    pub items: Vec<Item>,
+    /// Characters who live here
+    #[serde(default)]
+    pub npcs: Vec<Npc>,
     pub start: bool,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+impl Room {
+    /// The exit a player means by `input`: a direction in full or
+    /// abbreviated, or one of an exit's aliases, in any case.
+    pub fn find_exit(&self, input: &str) -> Option<&Exit> {
+        let input = input.trim();
+        if let Some(direction) = Direction::parse(input)
+            && let Some(exit) = self.exits.iter().find(|e| e.canonical_direction() == Some(direction))
+        {
+            return Some(exit);
+        }
+        self.exits.iter().find(|exit| exit.words().any(|word| word.eq_ignore_ascii_case(input)))
+    }
+
+    /// The exit with the door a player means by `input`: the way it leads,
+    /// as for [`Room::find_exit`], or the door's name. Nothing at all, or
+    /// just "door", means the only door in the room.
+    pub fn find_door(&self, input: &str) -> Option<&Exit> {
+        let input = input.trim();
+        let mut doors = self.exits.iter().filter(|exit| exit.door.is_some());
+        let named = self.find_exit(input)
+            .filter(|exit| exit.door.is_some())
+            .or_else(|| doors.clone().find(|exit| exit.door.as_ref().is_some_and(|d| d.name.eq_ignore_ascii_case(input))));
+        if named.is_some() || !(input.is_empty() || input.eq_ignore_ascii_case("door")) {
+            return named;
+        }
+        let door = doors.next();
+        if doors.next().is_none() { door } else { None }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
 pub struct Exit {
     pub direction: String,
     pub room_name: String,
+    /// Other words that take this exit, e.g. "gate" or "stairs"
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    #[serde(default)]
+    pub door: Option<Door>,
+}
+
+impl Exit {
+    /// The standard direction this exit goes in, if it isn't a custom one.
+    pub fn canonical_direction(&self) -> Option<Direction> {
+        Direction::parse(&self.direction)
+    }
+
+    /// The direction and every alias.
+    pub fn words(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.direction.as_str()).chain(self.aliases.iter().map(String::as_str))
+    }
+}
+
+// Room files leave out empty optional fields to stay readable, but the
+// network protocol (bincode) isn't self-describing and needs every field.
+impl Serialize for Room {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let terse = serializer.is_human_readable();
+        let mut room = serializer.serialize_struct("Room", 6)?;
+        room.serialize_field("name", &self.name)?;
+        room.serialize_field("description", &self.description)?;
+        room.serialize_field("exits", &self.exits)?;
+        optional_field(&mut room, terse, "items", &self.items, self.items.is_empty())?;
+        optional_field(&mut room, terse, "npcs", &self.npcs, self.npcs.is_empty())?;
+        room.serialize_field("start", &self.start)?;
+        room.end()
+    }
+}
+
+impl Serialize for Exit {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let terse = serializer.is_human_readable();
+        let mut exit = serializer.serialize_struct("Exit", 4)?;
+        exit.serialize_field("direction", &self.direction)?;
+        exit.serialize_field("room_name", &self.room_name)?;
+        optional_field(&mut exit, terse, "aliases", &self.aliases, self.aliases.is_empty())?;
+        optional_field(&mut exit, terse, "door", &self.door, self.door.is_none())?;
+        exit.end()
+    }
+}
+
+fn optional_field<S: SerializeStruct>(
+    s: &mut S,
+    terse: bool,
+    key: &'static str,
+    value: &impl Serialize,
+    empty: bool,
+) -> Result<(), S::Error> {
+    if terse && empty {
+        s.skip_field(key)
+    } else {
+        s.serialize_field(key, value)
+    }
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -21,8 +137,18 @@ pub enum RoomError {
     NotFound,
     #[error("Failed to load rooms")]
     LoadFailed,
-    #[error("Invalid exit in room: {0}")]
-    InvalidExit(String),
+    #[error("Failed to read {path}: {source}")]
+    Io { path: PathBuf, source: std::io::Error },
+    #[error("Failed to parse {path}: {source}")]
+    Parse { path: PathBuf, source: FormatError },
+    #[error("Failed to write {path}: {source}")]
+    Write { path: PathBuf, source: FormatError },
+    #[error("Unknown world file format for {0}; use .json, .toml, .ron or .yaml")]
+    UnknownFormat(PathBuf),
+    #[error("No zone files found in {0}")]
+    NoZoneFiles(PathBuf),
+    #[error("Invalid world: {0}")]
+    Invalid(ValidationReport),
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -30,28 +156,399 @@ pub struct RoomLibrary {
     rooms: HashMap<String, Room>,
 }
 
+/// A world as read from disk, whether or not it passed validation.
+#[derive(Debug, Clone)]
+pub struct LoadedWorld {
+    pub rooms: HashMap<String, Room>,
+    /// The file each room was read from
+    pub sources: HashMap<String, PathBuf>,
+    /// Each file read, with its rooms in file order
+    pub zones: Vec<(PathBuf, Vec<String>)>,
+    pub report: ValidationReport,
+}
+
+impl LoadedWorld {
+    /// Writes every room back to the file it came from, in the format its
+    /// extension names, keeping the order of rooms within each file.
+    /// Duplicate rooms only keep their first definition, so check the report
+    /// before saving. Change the paths in `zones` to save somewhere else.
+    ///
+    /// Files that already hold the same rooms are left alone; the others are
+    /// replaced atomically, so a failed save never leaves half a zone behind.
+    /// A rewritten file is generated from the rooms alone, so any comments in
+    /// a TOML, RON or YAML zone are lost. Returns the files written.
+    pub fn save(&self) -> Result<Vec<PathBuf>, RoomError> {
+        let mut written = Vec::new();
+        for (path, names) in &self.zones {
+            let format = WorldFormat::from_path(path).ok_or_else(|| RoomError::UnknownFormat(path.clone()))?;
+            let zone = ZoneRef(names.iter().filter_map(|name| self.rooms.get_key_value(name)).collect());
+            let write_error = |source| RoomError::Write { path: path.clone(), source };
+            if zone.is_saved_in(path, format) {
+                continue;
+            }
+            let text = format.render(&zone).map_err(write_error)?;
+            file_util::write_file_atomic(path, text.as_bytes()).map_err(|e| write_error(e.into()))?;
+            written.push(path.clone());
+        }
+        Ok(written)
+    }
+}
+
+/// Serializes rooms as a map in the given order.
+struct ZoneRef<'a>(Vec<(&'a String, &'a Room)>);
+
+impl ZoneRef<'_> {
+    /// True if `path` already holds exactly these rooms, in this order,
+    /// however it happens to be laid out.
+    fn is_saved_in(&self, path: &Path, format: WorldFormat) -> bool {
+        let Ok(data) = std::fs::read_to_string(path) else {
+            return false;
+        };
+        format.parse::<ZoneFile>(&data).is_ok_and(|ZoneFile(saved)| {
+            saved.len() == self.0.len() && saved.iter().zip(&self.0).all(|((name, room), (n, r))| name == *n && room == *r)
+        })
+    }
+}
+
+impl Serialize for ZoneRef<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.iter().copied())
+    }
+}
+
+/// The rooms in one zone file, in file order and keeping any repeated keys,
+/// which a plain `HashMap` would silently collapse.
+struct ZoneFile(Vec<(String, Room)>);
+
+impl<'de> Deserialize<'de> for ZoneFile {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ZoneVisitor;
+
+        impl<'de> Visitor<'de> for ZoneVisitor {
+            type Value = ZoneFile;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a map of room names to rooms")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut rooms = Vec::new();
+                while let Some(entry) = map.next_entry()? {
+                    rooms.push(entry);
+                }
+                Ok(ZoneFile(rooms))
+            }
+        }
+
+        deserializer.deserialize_map(ZoneVisitor)
+    }
+}
 
 impl RoomLibrary {
+    /// Loads `rooms.json` from the current directory.
     pub fn load() -> Result<HashMap<String, Room>, RoomError> {
-        let path = std::path::Path::new("rooms.json");
-        let rooms: HashMap<String, Room> = if path.exists() {
-            let data = std::fs::read_to_string(path)
-                .map_err(|_e| RoomError::LoadFailed)?;
-            serde_json::from_str(&data)
-                .map_err(|_e| RoomError::LoadFailed)?
+        Self::load_from("rooms.json")
+    }
+
+    /// Loads a world from a single rooms file, or from every zone file in a
+    /// directory (and its subdirectories), merged into one world. Files can
+    /// be JSON, TOML, RON or YAML, going by their extension, and can be mixed.
+    ///
+    /// Room names must be unique across all zone files, and exits may lead
+    /// to rooms in any zone. Fails with [`RoomError::Invalid`] listing every
+    /// error if the world doesn't validate; warnings are ignored.
+    pub fn load_from(path: impl AsRef<Path>) -> Result<HashMap<String, Room>, RoomError> {
+        let world = Self::check(path)?;
+        if world.report.has_errors() {
+            return Err(RoomError::Invalid(world.report));
+        }
+        Ok(world.rooms)
+    }
+
+    /// Reads and validates a world like [`RoomLibrary::load_from`], but
+    /// returns it along with its report even if it has errors. Only I/O and
+    /// parse failures are returned as errors.
+    pub fn check(path: impl AsRef<Path>) -> Result<LoadedWorld, RoomError> {
+        let path = path.as_ref();
+        let files = if path.is_dir() {
+            let files = zone_files(path)?;
+            if files.is_empty() {
+                return Err(RoomError::NoZoneFiles(path.to_path_buf()));
+            }
+            files
         } else {
-            return Err(RoomError::LoadFailed);
+            vec![path.to_path_buf()]
         };
 
-        // Validate the exits
-        for room in rooms.values() {
-            for exit in &room.exits {
-                if !rooms.contains_key(&exit.room_name) {
-                    return Err(RoomError::InvalidExit(exit.room_name.clone()));
+        // Merge the zones, remembering where each room came from
+        let mut rooms = HashMap::new();
+        let mut sources: HashMap<String, PathBuf> = HashMap::new();
+        let mut zones = Vec::new();
+        let mut duplicates = Vec::new();
+        for file in &files {
+            let format = WorldFormat::from_path(file).ok_or_else(|| RoomError::UnknownFormat(file.clone()))?;
+            let data = std::fs::read_to_string(file)
+                .map_err(|source| RoomError::Io { path: file.clone(), source })?;
+            let ZoneFile(zone) = format.parse(&data)
+                .map_err(|source| RoomError::Parse { path: file.clone(), source })?;
+            let mut names = Vec::new();
+            for (name, room) in zone {
+                if let Some(first) = sources.get(&name) {
+                    duplicates.push(Issue::DuplicateRoom { name, first: first.clone(), second: file.clone() });
+                    continue;
                 }
+                sources.insert(name.clone(), file.clone());
+                names.push(name.clone());
+                rooms.insert(name, room);
             }
+            zones.push((file.clone(), names));
+        }
+
+        let report = validate(&rooms, duplicates);
+        Ok(LoadedWorld { rooms, sources, zones, report })
+    }
+}
+
+/// Every zone file under `dir`, sorted so merging is deterministic.
+fn zone_files(dir: &Path) -> Result<Vec<PathBuf>, RoomError> {
+    let io_error = |source| RoomError::Io { path: dir.to_path_buf(), source };
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir).map_err(io_error)? {
+        let path = entry.map_err(io_error)?.path();
+        if path.is_dir() {
+            files.extend(zone_files(&path)?);
+        } else if WorldFormat::from_path(&path).is_some() {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+    use super::*;
+
+    fn write(path: &Path, json: &str) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, json).unwrap();
+    }
+
+    fn room(name: &str, exits: &[(&str, &str)]) -> String {
+        let exits = exits
+            .iter()
+            .map(|(direction, target)| format!(r#"{{ "direction": "{direction}", "room_name": "{target}" }}"#))
+            .join(", ");
+        format!(r#""{name}": {{ "name": "{name}", "description": "", "exits": [{exits}], "start": true }}"#)
+    }
+
+    #[test]
+    fn test_load_single_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("world.json");
+        write(&path, &format!("{{ {}, {} }}", room("A", &[("north", "B")]), room("B", &[])));
+        let rooms = RoomLibrary::load_from(&path).unwrap();
+        assert_eq!(rooms.len(), 2);
+        assert!(matches!(RoomLibrary::load_from(dir.path().join("missing.json")), Err(RoomError::Io { .. })));
+    }
+
+    #[test]
+    fn test_load_directory_with_cross_zone_exits() {
+        let dir = tempfile::tempdir().unwrap();
+        write(&dir.path().join("town.json"), &format!("{{ {} }}", room("Square", &[("east", "Forest Edge")])));
+        write(&dir.path().join("wilds/forest.json"), &format!("{{ {} }}", room("Forest Edge", &[("west", "Square")])));
+        write(&dir.path().join("notes.txt"), "not a zone");
+        let rooms = RoomLibrary::load_from(dir.path()).unwrap();
+        assert_eq!(rooms.keys().sorted().collect_vec(), ["Forest Edge", "Square"]);
+
+        // An exit into a zone that isn't there is caught
+        std::fs::remove_file(dir.path().join("wilds/forest.json")).unwrap();
+        let Err(RoomError::Invalid(report)) = RoomLibrary::load_from(dir.path()) else {
+            panic!("expected an invalid world");
+        };
+        assert_eq!(report.issues, [Issue::UnknownExit {
+            room: "Square".to_string(),
+            direction: "east".to_string(),
+            target: "Forest Edge".to_string(),
+        }]);
+    }
+
+    #[test]
+    fn test_duplicate_rooms_are_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        write(&dir.path().join("a.json"), &format!("{{ {}, {} }}", room("Hall", &[]), room("Hall", &[])));
+        write(&dir.path().join("b.json"), &format!("{{ {} }}", room("Hall", &[])));
+        let world = RoomLibrary::check(dir.path()).unwrap();
+        let a = dir.path().join("a.json");
+        let b = dir.path().join("b.json");
+        assert_eq!(world.report.issues, [
+            Issue::DuplicateRoom { name: "Hall".to_string(), first: a.clone(), second: a.clone() },
+            Issue::DuplicateRoom { name: "Hall".to_string(), first: a.clone(), second: b },
+        ]);
+        assert_eq!(world.sources["Hall"], a);
+        assert!(matches!(RoomLibrary::load_from(dir.path()), Err(RoomError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_parse_errors_keep_the_position() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("broken.json");
+        write(&path, "{\n  \"Hall\": { \"name\": \"Hall\" }\n}");
+        let err = RoomLibrary::load_from(&path).unwrap_err();
+        let RoomError::Parse { source: FormatError::Json(source), .. } = &err else {
+            panic!("expected a parse error, got {err:?}");
+        };
+        assert_eq!((source.line(), source.column()), (2, 28));
+        assert!(err.to_string().ends_with("at line 2 column 28"), "{err}");
+    }
+
+    #[test]
+    fn test_save_keeps_files_and_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let town = dir.path().join("town.json");
+        write(&town, &format!("{{ {}, {} }}", room("Square", &[("East", "Forest")]), room("Inn", &[])));
+        write(&dir.path().join("forest.json"), &format!("{{ {} }}", room("Forest", &[])));
+        let mut world = RoomLibrary::check(dir.path()).unwrap();
+        assert_eq!(world.save().unwrap(), Vec::<PathBuf>::new());
+        world.rooms.get_mut("Inn").unwrap().description = "Warm.".to_string();
+        assert_eq!(world.save().unwrap(), std::slice::from_ref(&town));
+        assert_eq!(fix(&mut world.rooms).len(), 2);
+        assert_eq!(world.save().unwrap().len(), 2);
+
+        let saved = std::fs::read_to_string(&town).unwrap();
+        assert!(saved.find("\"Square\"").unwrap() < saved.find("\"Inn\"").unwrap());
+        assert!(saved.starts_with("{\n    \"Square\": {\n        \"name\""), "{saved}");
+        let world = RoomLibrary::check(dir.path()).unwrap();
+        assert_eq!(world.rooms["Forest"].exits[0].direction, "west");
+        assert_eq!(world.sources["Forest"], dir.path().join("forest.json"));
+    }
+
+    #[test]
+    fn test_every_format_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("world.json");
+        let description = "A long description,\nwith \"quotes\", 'apostrophes' and: colons.";
+        let json = format!("{{ {}, {} }}", room("Herbert's Room", &[("north", "Main Hall")]), room("Main Hall", &[("south", "Herbert's Room")]));
+        write(&source, &json.replacen(r#""description": """#, &format!("\"description\": {description:?}"), 1));
+        let original = RoomLibrary::check(&source).unwrap();
+        assert_eq!(original.rooms["Herbert's Room"].description, description);
+
+        for format in WorldFormat::ALL {
+            let mut world = original.clone();
+            let path = dir.path().join(format!("converted.{format}"));
+            world.zones[0].0 = path.clone();
+            world.save().unwrap();
+            let converted = RoomLibrary::check(&path).unwrap();
+            assert_eq!(converted.rooms, original.rooms, "{format}");
+            assert_eq!(converted.zones[0].1, original.zones[0].1, "{format}");
         }
+    }
+
+    #[test]
+    fn test_mixed_formats_are_validated_together() {
+        let dir = tempfile::tempdir().unwrap();
+        write(&dir.path().join("town.yaml"), "Square:\n  name: Square\n  description: ''\n  exits:\n  - direction: east\n    room_name: Forest\n  start: true\n");
+        write(&dir.path().join("forest.toml"), "[Forest]\nname = \"Forest\"\ndescription = \"\"\nexits = []\nstart = false\n");
+        let world = RoomLibrary::check(dir.path()).unwrap();
+        assert_eq!(world.rooms.len(), 2);
+        assert_eq!(world.report.issues, [Issue::OneWayExit {
+            room: "Square".to_string(),
+            direction: "east".to_string(),
+            target: "Forest".to_string(),
+        }]);
 
-        Ok(rooms)
+        write(&dir.path().join("broken.ron"), "{ \"Cave\": (name: \"Cave\", }");
+        let err = RoomLibrary::check(dir.path()).unwrap_err();
+        assert!(matches!(err, RoomError::Parse { source: FormatError::RonRead(_), .. }), "{err}");
+        assert!(matches!(RoomLibrary::check(dir.path().join("notes.txt")), Err(RoomError::UnknownFormat(_))));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_find_exit() {
+        let mut room = test_util::room("Hall", true, &[("North", "North"), ("sw", "sw"), ("portal", "portal")]);
+        room.exits[1].aliases = vec!["Gate".to_string()];
+        room.exits[2].aliases = vec!["shimmer".to_string()];
+        let target = |input| room.find_exit(input).map(|e| e.room_name.as_str());
+        assert_eq!(target("n"), Some("North"));
+        assert_eq!(target(" NORTH "), Some("North"));
+        assert_eq!(target("southwest"), Some("sw"));
+        assert_eq!(target("gate"), Some("sw"));
+        assert_eq!(target("Portal"), Some("portal"));
+        assert_eq!(target("SHIMMER"), Some("portal"));
+        assert_eq!(target("s"), None);
+    }
+
+    #[test]
+    fn test_files_leave_out_empty_aliases() {
+        let exit = test_util::exit("north", "Hall");
+        assert_eq!(serde_json::to_string(&exit).unwrap(), r#"{"direction":"north","room_name":"Hall"}"#);
+        let with_alias = Exit { aliases: vec!["door".to_string()], ..exit.clone() };
+        assert_eq!(serde_json::to_string(&with_alias).unwrap(), r#"{"direction":"north","room_name":"Hall","aliases":["door"]}"#);
+        let door = Door { name: "oak door".to_string(), id: None, state: DoorState::Closed, key: None, closes_after: None };
+        let with_door = Exit { door: Some(door), ..exit.clone() };
+        let json = serde_json::to_string(&with_door).unwrap();
+        assert_eq!(json, r#"{"direction":"north","room_name":"Hall","door":{"name":"oak door","state":"closed"}}"#);
+        assert_eq!(serde_json::from_str::<Exit>(&json).unwrap(), with_door);
+    }
+
+    #[test]
+    fn test_items_and_npcs_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("world.json");
+        let items = r#"[
+            { "name": "lamp", "description": "Brass.", "properties": { "lit": false, "weight": 2, "value": 1.5, "colour": "gold" } },
+            { "name": "pebble" }
+        ]"#;
+        let npcs = r#"[{ "name": "Old Tom", "wander": 2, "greeting": "Evening.", "responses": { "lamp": "Mind the oil." } }]"#;
+        let fields = format!(r#""items": {items}, "npcs": {npcs}, "start""#);
+        write(&source, &format!("{{ {} }}", room("Hall", &[]).replace(r#""start""#, &fields)));
+        let original = RoomLibrary::check(&source).unwrap();
+        let lamp = &original.rooms["Hall"].items[0];
+        assert_eq!(lamp.properties["lit"], Property::Bool(false));
+        assert_eq!(lamp.properties["weight"].as_int(), Some(2));
+        assert_eq!(lamp.properties["value"].as_float(), Some(1.5));
+        assert_eq!(lamp.properties["colour"].as_str(), Some("gold"));
+        assert!(original.rooms["Hall"].items[1].is_called(" Pebble"));
+        assert_eq!(original.rooms["Hall"].npcs[0].reply_to("a lamp?"), Some("Mind the oil."));
+
+        for format in WorldFormat::ALL {
+            let mut world = original.clone();
+            let path = dir.path().join(format!("converted.{format}"));
+            world.zones[0].0 = path.clone();
+            world.save().unwrap();
+            assert_eq!(RoomLibrary::check(&path).unwrap().rooms, original.rooms, "{format}");
+        }
+        // The protocol can't rely on the values describing themselves
+        let bytes = bincode::serialize(&original.rooms["Hall"]).unwrap();
+        assert_eq!(bincode::deserialize::<Room>(&bytes).unwrap(), original.rooms["Hall"]);
+        assert_eq!(serde_json::to_string(&original.rooms["Hall"].items[1]).unwrap(), r#"{"name":"pebble"}"#);
+    }
+
+    #[test]
+    fn test_find_door() {
+        let exit = |direction: &str, door: Option<&str>| Exit {
+            door: door.map(|name| Door { name: name.to_string(), id: None, state: DoorState::Locked, key: Some("brass key".to_string()), closes_after: None }),
+            ..test_util::exit(direction, direction)
+        };
+        let mut room = Room { exits: vec![exit("north", Some("Oak Door")), exit("east", None)], ..test_util::room("Hall", true, &[]) };
+        let target = |room: &Room, input| room.find_door(input).map(|e| e.room_name.clone());
+        assert_eq!(target(&room, "n").as_deref(), Some("north"));
+        assert_eq!(target(&room, "oak door").as_deref(), Some("north"));
+        assert_eq!(target(&room, "").as_deref(), Some("north"));
+        assert_eq!(target(&room, "door").as_deref(), Some("north"));
+        assert_eq!(target(&room, "east"), None);
+
+        // With two doors, players have to say which
+        room.exits.push(exit("down", Some("trapdoor")));
+        assert_eq!(target(&room, ""), None);
+        assert_eq!(target(&room, "door"), None);
+        assert_eq!(target(&room, "TRAPDOOR").as_deref(), Some("down"));
+    }
+
+    #[test]
+    fn test_empty_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(matches!(RoomLibrary::load_from(dir.path()), Err(RoomError::NoZoneFiles(_))));
+    }
+}
@@ -0,0 +1,227 @@
+use std::{collections::{HashMap, HashSet, VecDeque}, fmt::Write};
+use itertools::Itertools;
+use crate::{Direction, Room};
+
+/// Longest room name shown in an ASCII map; longer ones are cut short.
+const MAX_LABEL: usize = 16;
+
+fn dot_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// The world as a Graphviz digraph. Start rooms are filled in, exits that
+/// can be walked both ways are drawn once with an arrow at each end, and
+/// one-way exits are dashed. Each exit pairs with at most one exit back,
+/// preferring the opposite direction, so parallel exits between two rooms
+/// are drawn separately.
+pub fn to_dot(rooms: &HashMap<String, Room>) -> String {
+    let mut dot = String::from("digraph world {\n    node [shape=box];\n");
+    for name in rooms.keys().sorted() {
+        let style = if rooms[name].start { " [style=filled, fillcolor=palegreen]" } else { "" };
+        writeln!(dot, "    \"{}\"{style};", dot_escape(name)).unwrap();
+    }
+    // Exits already drawn as the far end of a two-way pair
+    let mut drawn: HashSet<(&String, usize)> = HashSet::new();
+    for name in rooms.keys().sorted() {
+        for (i, exit) in rooms[name].exits.iter().enumerate() {
+            if !drawn.insert((name, i)) {
+                continue;
+            }
+            let from = dot_escape(name);
+            let to = dot_escape(&exit.room_name);
+            let direction = dot_escape(&exit.direction);
+            let opposite = exit.canonical_direction().map(Direction::opposite);
+            let back = rooms
+                .get_key_value(&exit.room_name)
+                .filter(|(target, _)| *target != name)
+                .and_then(|(target, room)| {
+                    let free = (0..room.exits.len())
+                        .filter(|j| room.exits[*j].room_name == *name && !drawn.contains(&(target, *j)))
+                        .collect_vec();
+                    let matching = free.iter().find(|j| opposite.is_some() && room.exits[**j].canonical_direction() == opposite);
+                    matching.or(free.first()).map(|j| (target, *j))
+                });
+            match back {
+                Some((target, j)) => {
+                    drawn.insert((target, j));
+                    writeln!(
+                        dot,
+                        "    \"{from}\" -> \"{to}\" [dir=both, taillabel=\"{direction}\", headlabel=\"{}\"];",
+                        dot_escape(&rooms[target].exits[j].direction)
+                    ).unwrap()
+                }
+                None => writeln!(dot, "    \"{from}\" -> \"{to}\" [label=\"{direction}\", style=dashed];").unwrap(),
+            }
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// Grid offset for a compass direction, with north being up.
+fn compass_offset(direction: &str) -> Option<(i32, i32)> {
+    Direction::parse(direction)?.grid_offset()
+}
+
+/// A best-effort map of the world on a grid, laid out by following compass
+/// exits from the start rooms. Start rooms are marked with `*`. Rooms that
+/// can't be reached that way, or that would land on a square already taken,
+/// start a separate map, and exits that can't be drawn are listed at the end.
+pub fn to_ascii_map(rooms: &HashMap<String, Room>) -> String {
+    // Place every room, one connected group at a time
+    let order = rooms.keys().sorted_by_key(|name| (!rooms[*name].start, *name)).collect_vec();
+    let mut placed: HashMap<&String, (usize, (i32, i32))> = HashMap::new();
+    let mut groups: Vec<HashMap<(i32, i32), &String>> = Vec::new();
+    for first in order {
+        if placed.contains_key(first) {
+            continue;
+        }
+        let group = groups.len();
+        let mut cells = HashMap::from([((0, 0), first)]);
+        placed.insert(first, (group, (0, 0)));
+        let mut queue = VecDeque::from([(first, (0, 0))]);
+        while let Some((name, (x, y))) = queue.pop_front() {
+            for exit in &rooms[name].exits {
+                let Some((dx, dy)) = compass_offset(&exit.direction) else {
+                    continue;
+                };
+                let Some((target, _)) = rooms.get_key_value(&exit.room_name) else {
+                    continue;
+                };
+                let cell = (x + dx, y + dy);
+                if placed.contains_key(target) || cells.contains_key(&cell) {
+                    continue;
+                }
+                cells.insert(cell, target);
+                placed.insert(target, (group, cell));
+                queue.push_back((target, cell));
+            }
+        }
+        groups.push(cells);
+    }
+
+    let width = rooms.keys().map(|name| name.chars().count() + 1).max().unwrap_or(1).min(MAX_LABEL);
+    let cell_width = width + 2;
+    let mut map = String::new();
+    let mut not_shown = Vec::new();
+    for (group, cells) in groups.iter().enumerate() {
+        let min_x = cells.keys().map(|(x, _)| *x).min().unwrap();
+        let min_y = cells.keys().map(|(_, y)| *y).min().unwrap();
+        let columns = (cells.keys().map(|(x, _)| *x).max().unwrap() - min_x + 1) as usize;
+        let rows = (cells.keys().map(|(_, y)| *y).max().unwrap() - min_y + 1) as usize;
+        let mut canvas = vec![vec![' '; columns * (cell_width + 3) - 3]; rows * 2 - 1];
+        let origin = |(x, y): (i32, i32)| ((y - min_y) as usize * 2, (x - min_x) as usize * (cell_width + 3));
+
+        for (cell, name) in cells.iter().sorted() {
+            let (row, col) = origin(*cell);
+            let marker = if rooms[*name].start { "*" } else { "" };
+            let label: String = format!("{marker}{name}").chars().take(width).collect();
+            let boxed = format!("[{label:width$}]");
+            for (i, c) in boxed.chars().enumerate() {
+                canvas[row][col + i] = c;
+            }
+
+            for exit in &rooms[*name].exits {
+                let drawn = compass_offset(&exit.direction).filter(|(dx, dy)| {
+                    placed.get(&exit.room_name) == Some(&(group, (cell.0 + dx, cell.1 + dy)))
+                });
+                let Some((dx, dy)) = drawn else {
+                    not_shown.push(format!("{} {} -> {}", name, exit.direction, exit.room_name));
+                    continue;
+                };
+                // Draw from the top-left room of the pair, so each link is drawn once
+                let (from, dx, dy) = if (dy, dx) < (0, 0) { ((cell.0 + dx, cell.1 + dy), -dx, -dy) } else { (*cell, dx, dy) };
+                let (row, col) = origin(from);
+                match (dx, dy) {
+                    (1, 0) => canvas[row][col + cell_width..col + cell_width + 3].fill('-'),
+                    (0, 1) => canvas[row + 1][col + cell_width / 2] = '|',
+                    (1, 1) | (-1, 1) => {
+                        let (col, line) = if dx == 1 { (col + cell_width + 1, '\\') } else { (col - 2, '/') };
+                        let existing = canvas[row + 1][col];
+                        canvas[row + 1][col] = if existing == ' ' || existing == line { line } else { 'X' };
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        if group > 0 {
+            map.push('\n');
+        }
+        for line in canvas {
+            map.push_str(line.into_iter().collect::<String>().trim_end());
+            map.push('\n');
+        }
+    }
+
+    if !not_shown.is_empty() {
+        map.push_str("\nNot shown:\n");
+        for exit in not_shown {
+            writeln!(map, "  {exit}").unwrap();
+        }
+    }
+    map
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world() -> HashMap<String, Room> {
+        crate::test_util::world(&[
+            ("Hall", true, &[("north", "Study"), ("east", "Kitchen"), ("up", "Attic")]),
+            ("Study", false, &[("south", "Hall"), ("southeast", "Kitchen")]),
+            ("Kitchen", false, &[("west", "Hall")]),
+            ("Attic", false, &[]),
+        ])
+    }
+
+    #[test]
+    fn test_dot() {
+        assert_eq!(to_dot(&world()), concat!(
+            "digraph world {\n",
+            "    node [shape=box];\n",
+            "    \"Attic\";\n",
+            "    \"Hall\" [style=filled, fillcolor=palegreen];\n",
+            "    \"Kitchen\";\n",
+            "    \"Study\";\n",
+            "    \"Hall\" -> \"Study\" [dir=both, taillabel=\"north\", headlabel=\"south\"];\n",
+            "    \"Hall\" -> \"Kitchen\" [dir=both, taillabel=\"east\", headlabel=\"west\"];\n",
+            "    \"Hall\" -> \"Attic\" [label=\"up\", style=dashed];\n",
+            "    \"Study\" -> \"Kitchen\" [label=\"southeast\", style=dashed];\n",
+            "}\n",
+        ));
+    }
+
+    #[test]
+    fn test_dot_pairs_parallel_exits() {
+        let rooms = crate::test_util::world(&[
+            ("Hall", true, &[("hatch", "Cellar"), ("down", "Cellar"), ("ladder", "Cellar")]),
+            ("Cellar", false, &[("trapdoor", "Hall"), ("up", "Hall")]),
+        ]);
+        assert_eq!(to_dot(&rooms), concat!(
+            "digraph world {\n",
+            "    node [shape=box];\n",
+            "    \"Cellar\";\n",
+            "    \"Hall\" [style=filled, fillcolor=palegreen];\n",
+            "    \"Cellar\" -> \"Hall\" [dir=both, taillabel=\"trapdoor\", headlabel=\"hatch\"];\n",
+            "    \"Cellar\" -> \"Hall\" [dir=both, taillabel=\"up\", headlabel=\"down\"];\n",
+            "    \"Hall\" -> \"Cellar\" [label=\"ladder\", style=dashed];\n",
+            "}\n",
+        ));
+    }
+
+    #[test]
+    fn test_ascii_map() {
+        assert_eq!(to_ascii_map(&world()), concat!(
+            "[Study   ]\n",
+            "     |     \\\n",
+            "[*Hall   ]---[Kitchen ]\n",
+            "\n",
+            "[Attic   ]\n",
+            "\n",
+            "Not shown:\n",
+            "  Hall up -> Attic\n",
+        ));
+    }
+}
@@ -0,0 +1,119 @@
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use serde::{ser::SerializeStruct, Deserialize, Serialize, Serializer};
+use crate::{optional_field, Room};
+
+/// A non-player character. NPCs are defined in their home room, and may
+/// wander a few rooms from it once the world is running.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Npc {
+    /// Unique across the world
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// How many exits from home they may wander; 0 keeps them at home
+    #[serde(default)]
+    pub wander: u32,
+    /// Said to players who come into the room
+    #[serde(default)]
+    pub greeting: Option<String>,
+    /// Replies to players who say a keyword in the room, keyed by keyword
+    #[serde(default)]
+    pub responses: BTreeMap<String, String>,
+}
+
+impl Npc {
+    /// The reply to the first keyword, in keyword order, that `message`
+    /// contains as whole words, ignoring case and punctuation.
+    pub fn reply_to(&self, message: &str) -> Option<&str> {
+        let message = words(message);
+        self.responses
+            .iter()
+            .find(|(keyword, _)| message.contains(&words(keyword)))
+            .map(|(_, reply)| reply.as_str())
+    }
+}
+
+/// Lowercase words separated by single spaces, with a space at each end so
+/// that matches line up with whole words.
+fn words(text: &str) -> String {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric() && c != '\'')
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    format!(" {} ", words.join(" "))
+}
+
+impl Serialize for Npc {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let terse = serializer.is_human_readable();
+        let mut npc = serializer.serialize_struct("Npc", 5)?;
+        npc.serialize_field("name", &self.name)?;
+        optional_field(&mut npc, terse, "description", &self.description, self.description.is_empty())?;
+        optional_field(&mut npc, terse, "wander", &self.wander, self.wander == 0)?;
+        optional_field(&mut npc, terse, "greeting", &self.greeting, self.greeting.is_none())?;
+        optional_field(&mut npc, terse, "responses", &self.responses, self.responses.is_empty())?;
+        npc.end()
+    }
+}
+
+/// Every room that can be walked to from `from` in at most `steps` exits,
+/// including `from` itself.
+pub fn rooms_within(rooms: &HashMap<String, Room>, from: &str, steps: u32) -> HashSet<String> {
+    let mut reached = HashSet::from([from.to_string()]);
+    let mut queue = VecDeque::from([(from, 0)]);
+    while let Some((name, distance)) = queue.pop_front() {
+        if distance == steps {
+            continue;
+        }
+        for exit in rooms.get(name).map(|room| room.exits.as_slice()).unwrap_or_default() {
+            if rooms.contains_key(&exit.room_name) && reached.insert(exit.room_name.clone()) {
+                queue.push_back((&exit.room_name, distance + 1));
+            }
+        }
+    }
+    reached
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    #[test]
+    fn test_reply_to() {
+        let npc = Npc {
+            name: "Old Tom".to_string(),
+            description: String::new(),
+            wander: 0,
+            greeting: None,
+            responses: [("cellar key", "Lost it years ago."), ("hello", "Evening.")]
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        };
+        assert_eq!(npc.reply_to("Hello, Tom!"), Some("Evening."));
+        assert_eq!(npc.reply_to("where's the CELLAR  key?"), Some("Lost it years ago."));
+        assert_eq!(npc.reply_to("othello"), None);
+        assert_eq!(npc.reply_to("the cellar has a key"), None);
+    }
+
+    #[test]
+    fn test_rooms_within() {
+        // A corridor: A - B - C - D
+        let rooms = test_util::world(&[
+            ("A", true, &[("B", "B")]),
+            ("B", true, &[("A", "A"), ("C", "C")]),
+            ("C", true, &[("B", "B"), ("D", "D")]),
+            ("D", true, &[("C", "C")]),
+        ]);
+        let within = |from, steps| {
+            let mut names: Vec<String> = rooms_within(&rooms, from, steps).into_iter().collect();
+            names.sort();
+            names
+        };
+        assert_eq!(within("B", 0), ["B"]);
+        assert_eq!(within("B", 1), ["A", "B", "C"]);
+        assert_eq!(within("A", 9), ["A", "B", "C", "D"]);
+    }
+}
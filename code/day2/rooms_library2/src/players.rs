@@ -0,0 +1,218 @@
+use std::{collections::BTreeMap, path::{Path, PathBuf}};
+use file_util::{lock_file, write_file_atomic};
+use serde::{Deserialize, Serialize};
+use crate::Item;
+
+/// What a player keeps between sessions.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PlayerState {
+    pub username: String,
+    /// Where they were when last saved
+    pub room: String,
+    #[serde(default)]
+    pub inventory: Vec<Item>,
+    /// Seconds since the Unix epoch
+    pub first_seen: u64,
+    /// Seconds since the Unix epoch
+    pub last_seen: u64,
+    /// Total seconds spent in the world, over every session
+    #[serde(default)]
+    pub play_time_secs: u64,
+}
+
+impl PlayerState {
+    /// A player who has just arrived for the first time.
+    pub fn new(username: &str, room: &str, now: u64) -> Self {
+        Self {
+            username: username.to_string(),
+            room: room.to_string(),
+            inventory: Vec::new(),
+            first_seen: now,
+            last_seen: now,
+            play_time_secs: 0,
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum PlayerStoreError {
+    #[error("Player store I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Player store is not valid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// Somewhere to keep players' progress between sessions.
+pub trait PlayerStore: Send {
+    fn load(&mut self, username: &str) -> Result<Option<PlayerState>, PlayerStoreError>;
+    /// Every player saved, in no particular order.
+    fn load_all(&mut self) -> Result<Vec<PlayerState>, PlayerStoreError>;
+    /// Saves several players at once, replacing what was stored for each.
+    fn save_all(&mut self, players: &[PlayerState]) -> Result<(), PlayerStoreError>;
+
+    fn save(&mut self, player: &PlayerState) -> Result<(), PlayerStoreError> {
+        self.save_all(std::slice::from_ref(player))
+    }
+}
+
+/// Every player in one JSON object, keyed by username, so the file is easy
+/// to read and fix by hand. Saving someone rewrites the whole file: it's
+/// re-read under an exclusive lock first, so players saved by another server
+/// sharing the file are kept, and written with
+/// [`write_file_atomic`].
+pub struct JsonPlayerStore {
+    path: PathBuf,
+}
+
+impl JsonPlayerStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn read(&self) -> Result<BTreeMap<String, PlayerState>, PlayerStoreError> {
+        match std::fs::read_to_string(&self.path) {
+            Ok(data) => Ok(serde_json::from_str(&data)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+impl PlayerStore for JsonPlayerStore {
+    fn load(&mut self, username: &str) -> Result<Option<PlayerState>, PlayerStoreError> {
+        let _lock = lock_file(&self.path, false)?;
+        Ok(self.read()?.remove(username))
+    }
+
+    fn load_all(&mut self) -> Result<Vec<PlayerState>, PlayerStoreError> {
+        let _lock = lock_file(&self.path, false)?;
+        Ok(self.read()?.into_values().collect())
+    }
+
+    fn save_all(&mut self, players: &[PlayerState]) -> Result<(), PlayerStoreError> {
+        let _lock = lock_file(&self.path, true)?;
+        let mut stored = self.read()?;
+        for player in players {
+            stored.insert(player.username.clone(), player.clone());
+        }
+        let data = serde_json::to_string_pretty(&stored)?;
+        Ok(write_file_atomic(&self.path, data.as_bytes())?)
+    }
+}
+
+/// Players kept in a map for as long as the server runs: `--players memory`,
+/// for demos where everyone should start from scratch, and for tests.
+#[derive(Default)]
+pub struct MemoryPlayerStore {
+    players: BTreeMap<String, PlayerState>,
+}
+
+impl MemoryPlayerStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl PlayerStore for MemoryPlayerStore {
+    fn load(&mut self, username: &str) -> Result<Option<PlayerState>, PlayerStoreError> {
+        Ok(self.players.get(username).cloned())
+    }
+
+    fn load_all(&mut self) -> Result<Vec<PlayerState>, PlayerStoreError> {
+        Ok(self.players.values().cloned().collect())
+    }
+
+    fn save_all(&mut self, players: &[PlayerState]) -> Result<(), PlayerStoreError> {
+        for player in players {
+            self.players.insert(player.username.clone(), player.clone());
+        }
+        Ok(())
+    }
+}
+
+/// Which [`PlayerStore`] to use, written like the server's user store
+/// setting: `memory`, `json:PATH`, or a path. Players can't be kept in
+/// SQLite, so `sqlite:` and `.db` paths are refused.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlayerStoreConfig {
+    Json(PathBuf),
+    Memory,
+}
+
+impl Default for PlayerStoreConfig {
+    fn default() -> Self {
+        PlayerStoreConfig::Json(PathBuf::from("players.json"))
+    }
+}
+
+impl std::str::FromStr for PlayerStoreConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "memory" {
+            return Ok(PlayerStoreConfig::Memory);
+        }
+        if let Some(path) = s.strip_prefix("json:") {
+            return Ok(PlayerStoreConfig::Json(PathBuf::from(path)));
+        }
+        let path = PathBuf::from(s);
+        let sqlite = matches!(path.extension().and_then(|e| e.to_str()), Some("db" | "sqlite" | "sqlite3"));
+        if s.starts_with("sqlite:") || sqlite {
+            return Err(format!("{s}: players can only be kept in JSON files or in memory"));
+        }
+        Ok(PlayerStoreConfig::Json(path))
+    }
+}
+
+impl PlayerStoreConfig {
+    pub fn open(&self) -> Box<dyn PlayerStore> {
+        match self {
+            PlayerStoreConfig::Json(path) => Box::new(JsonPlayerStore::new(path)),
+            PlayerStoreConfig::Memory => Box::new(MemoryPlayerStore::new()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_store_keeps_other_players() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("players.json");
+        let mut store = JsonPlayerStore::new(&path);
+        assert_eq!(store.load("pat").unwrap(), None);
+
+        let mut pat = PlayerState::new("pat", "Hall", 100);
+        let lamp = Item { name: "lamp".to_string(), description: String::new(), properties: Default::default() };
+        pat.inventory.push(lamp);
+        store.save_all(&[pat.clone(), PlayerState::new("bob", "Study", 200)]).unwrap();
+
+        pat.room = "Cellar".to_string();
+        pat.play_time_secs = 60;
+        store.save(&pat).unwrap();
+
+        // A fresh store reads what the last one wrote
+        let mut store = JsonPlayerStore::new(&path);
+        assert_eq!(store.load("pat").unwrap(), Some(pat));
+        assert_eq!(store.load("bob").unwrap().unwrap().room, "Study");
+        let mut names: Vec<String> = store.load_all().unwrap().into_iter().map(|p| p.username).collect();
+        names.sort();
+        assert_eq!(names, ["bob", "pat"]);
+        assert!(!dir.path().read_dir().unwrap().any(|e| e.unwrap().file_name().to_string_lossy().contains(".tmp")));
+    }
+
+    #[test]
+    fn test_store_config() {
+        assert_eq!("memory".parse::<PlayerStoreConfig>().unwrap(), PlayerStoreConfig::Memory);
+        assert_eq!("json:p.json".parse::<PlayerStoreConfig>().unwrap(), PlayerStoreConfig::Json(PathBuf::from("p.json")));
+        assert_eq!("saves/p.json".parse::<PlayerStoreConfig>().unwrap(), PlayerStoreConfig::Json(PathBuf::from("saves/p.json")));
+        assert!("sqlite:players.db".parse::<PlayerStoreConfig>().is_err());
+        assert!("players.db".parse::<PlayerStoreConfig>().is_err());
+    }
+}
@@ -0,0 +1,29 @@
+//! Builders for the small worlds the tests in this crate walk around.
+
+use std::collections::HashMap;
+use crate::{Exit, Room};
+
+/// Name, whether it's a start room, and (direction, target) exits
+pub(crate) type RoomSpec<'a> = (&'a str, bool, &'a [(&'a str, &'a str)]);
+
+/// A plain exit: no aliases and no door.
+pub(crate) fn exit(direction: &str, target: &str) -> Exit {
+    Exit { direction: direction.to_string(), room_name: target.to_string(), aliases: Vec::new(), door: None }
+}
+
+/// An empty room with plain exits.
+pub(crate) fn room(name: &str, start: bool, exits: &[(&str, &str)]) -> Room {
+    Room {
+        name: name.to_string(),
+        description: String::new(),
+        exits: exits.iter().map(|(direction, target)| exit(direction, target)).collect(),
+        items: Vec::new(),
+        npcs: Vec::new(),
+        start,
+    }
+}
+
+/// Rooms keyed by name, the way the loader stores them.
+pub(crate) fn world(rooms: &[RoomSpec]) -> HashMap<String, Room> {
+    rooms.iter().map(|(name, start, exits)| (name.to_string(), room(name, *start, exits))).collect()
+}
@@ -0,0 +1,384 @@
+use std::{collections::{HashMap, HashSet, VecDeque}, fmt, path::PathBuf};
+use itertools::Itertools;
+use crate::{door_id, Direction, Door, DoorState, Exit, Room};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// The world can't be used until this is fixed
+    Error,
+    /// Probably a mistake, but the world still works
+    Warning,
+}
+
+/// One problem found in a world.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Issue {
+    /// A room name defined more than once, either in two zone files or twice in one
+    DuplicateRoom { name: String, first: PathBuf, second: PathBuf },
+    /// The key a room is stored under isn't the room's own `name`
+    NameMismatch { key: String, name: String },
+    UnknownExit { room: String, direction: String, target: String },
+    /// Two exits from one room share a direction or alias, ignoring case and
+    /// abbreviations
+    DuplicateDirection { room: String, direction: String },
+    NoStartRoom,
+    /// Can't be reached by walking from any start room. Only a warning, since
+    /// builders often add rooms before the exits that lead to them
+    Unreachable { room: String },
+    /// The target room has no exit leading back
+    OneWayExit { room: String, direction: String, target: String },
+    /// The exits sharing a door disagree on its name, how it starts out, its
+    /// key or when it closes by itself. Door issues about sides name the
+    /// door by its [`door_id`](crate::door_id)
+    DoorMismatch { door: String },
+    /// A door on more than the two sides of one doorway
+    DoorReused { door: String },
+    /// The two exits sharing a door don't lead to each other's rooms
+    DoorNotReciprocal { door: String },
+    /// A locked door that nothing can open
+    LockedWithoutKey { door: String },
+    /// A door on an exit whose way back doesn't have it, so players can walk
+    /// around it
+    OneSidedDoor { room: String, direction: String, door: String },
+    /// No item anywhere in the world is the key to a door
+    UnknownKey { door: String, key: String },
+    /// Two NPCs share a name, ignoring case
+    DuplicateNpc { name: String, first: String, second: String },
+}
+
+impl Issue {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Issue::OneWayExit { .. }
+            | Issue::Unreachable { .. }
+            | Issue::LockedWithoutKey { .. }
+            | Issue::OneSidedDoor { .. }
+            | Issue::UnknownKey { .. } => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Issue::DuplicateRoom { name, first, second } => {
+                write!(f, "room '{name}' is defined in both {} and {}", first.display(), second.display())
+            }
+            Issue::NameMismatch { key, name } => write!(f, "room '{key}' is named '{name}'"),
+            Issue::UnknownExit { room, direction, target } => {
+                write!(f, "room '{room}' has exit {direction} to unknown room '{target}'")
+            }
+            Issue::DuplicateDirection { room, direction } => {
+                write!(f, "room '{room}' has more than one exit {direction}")
+            }
+            Issue::NoStartRoom => write!(f, "no room is marked as a start room"),
+            Issue::Unreachable { room } => write!(f, "room '{room}' can't be reached from any start room"),
+            Issue::OneWayExit { room, direction, target } => {
+                write!(f, "exit {direction} from '{room}' to '{target}' is one-way")
+            }
+            Issue::DoorMismatch { door } => write!(f, "the sides of door '{door}' don't agree on how it works"),
+            Issue::DoorReused { door } => write!(f, "door '{door}' is on more than two exits"),
+            Issue::DoorNotReciprocal { door } => write!(f, "the sides of door '{door}' aren't exits back to each other"),
+            Issue::LockedWithoutKey { door } => write!(f, "door '{door}' is locked but has no key"),
+            Issue::OneSidedDoor { room, direction, door } => {
+                write!(f, "door '{door}' on exit {direction} from '{room}' isn't on the way back")
+            }
+            Issue::UnknownKey { door, key } => write!(f, "door '{door}' needs key '{key}', which isn't in any room"),
+            Issue::DuplicateNpc { name, first, second } => {
+                write!(f, "NPC '{name}' lives in both '{first}' and '{second}'")
+            }
+        }
+    }
+}
+
+/// Every problem found in a world, errors first.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValidationReport {
+    pub issues: Vec<Issue>,
+}
+
+impl ValidationReport {
+    pub fn errors(&self) -> impl Iterator<Item = &Issue> {
+        self.issues.iter().filter(|i| i.severity() == Severity::Error)
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &Issue> {
+        self.issues.iter().filter(|i| i.severity() == Severity::Warning)
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.errors().next().is_some()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let errors = self.errors().count();
+        let warnings = self.issues.len() - errors;
+        write!(f, "{errors} error(s), {warnings} warning(s)")?;
+        for issue in &self.issues {
+            let label = match issue.severity() {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+            };
+            write!(f, "\n  {label}: {issue}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks a loaded world. `issues` are problems already found while loading
+/// it, such as duplicate rooms, and are kept at the front of the report.
+pub fn validate(rooms: &HashMap<String, Room>, mut issues: Vec<Issue>) -> ValidationReport {
+    let names = rooms.keys().sorted().collect_vec();
+
+    for key in &names {
+        let room = &rooms[*key];
+        if room.name != **key {
+            issues.push(Issue::NameMismatch { key: key.to_string(), name: room.name.clone() });
+        }
+        let mut words = HashSet::new();
+        for exit in &room.exits {
+            // Compared as players type them, so "N" clashes with "north"
+            for word in exit.words() {
+                let canonical = Direction::parse(word).map_or_else(|| word.to_lowercase(), |d| d.name().to_string());
+                if !words.insert(canonical) {
+                    issues.push(Issue::DuplicateDirection { room: key.to_string(), direction: word.to_string() });
+                }
+            }
+            if !rooms.contains_key(&exit.room_name) {
+                issues.push(Issue::UnknownExit {
+                    room: key.to_string(),
+                    direction: exit.direction.clone(),
+                    target: exit.room_name.clone(),
+                });
+            }
+        }
+    }
+
+    let mut npcs: HashMap<String, &String> = HashMap::new();
+    for key in &names {
+        for npc in &rooms[*key].npcs {
+            if let Some(first) = npcs.insert(npc.name.to_lowercase(), key) {
+                issues.push(Issue::DuplicateNpc { name: npc.name.clone(), first: first.clone(), second: key.to_string() });
+            }
+        }
+    }
+
+    // The sides of each door, as the server will pair them up
+    let mut doors: HashMap<String, Vec<(&String, &Exit, &Door)>> = HashMap::new();
+    for key in &names {
+        for exit in &rooms[*key].exits {
+            if let (Some(id), Some(door)) = (door_id(rooms, key, exit), &exit.door) {
+                doors.entry(id).or_default().push((*key, exit, door));
+            }
+        }
+    }
+    for (id, sides) in doors.iter().sorted_by_key(|(id, _)| *id) {
+        let (room, exit, door) = sides[0];
+        if sides.len() > 2 {
+            issues.push(Issue::DoorReused { door: id.clone() });
+        }
+        if let [(first, there, _), (second, back, _)] = sides[..]
+            && (there.room_name != *second || back.room_name != *first)
+        {
+            issues.push(Issue::DoorNotReciprocal { door: id.clone() });
+        }
+        let works = |d: &Door| (d.name.to_lowercase(), d.state, d.key.clone(), d.closes_after);
+        if sides.iter().any(|(_, _, other)| works(other) != works(door)) {
+            issues.push(Issue::DoorMismatch { door: id.clone() });
+        }
+        match &door.key {
+            None if door.state == DoorState::Locked => issues.push(Issue::LockedWithoutKey { door: door.name.clone() }),
+            Some(key) if !rooms.values().flat_map(|room| &room.items).any(|item| item.is_called(key)) => {
+                issues.push(Issue::UnknownKey { door: door.name.clone(), key: key.clone() });
+            }
+            _ => {}
+        }
+        let leads_back = rooms.get(&exit.room_name).is_some_and(|target| target.exits.iter().any(|e| e.room_name == *room));
+        if sides.len() == 1 && leads_back && exit.room_name != *room {
+            issues.push(Issue::OneSidedDoor {
+                room: room.clone(),
+                direction: exit.direction.clone(),
+                door: door.name.clone(),
+            });
+        }
+    }
+
+    // Walk out from every start room to find the ones nobody can get to
+    let mut reached: HashSet<&String> = names.iter().copied().filter(|name| rooms[*name].start).collect();
+    if reached.is_empty() {
+        issues.push(Issue::NoStartRoom);
+    } else {
+        let mut queue: VecDeque<&String> = reached.iter().copied().collect();
+        while let Some(name) = queue.pop_front() {
+            for exit in &rooms[name].exits {
+                if let Some((target, _)) = rooms.get_key_value(&exit.room_name)
+                    && reached.insert(target)
+                {
+                    queue.push_back(target);
+                }
+            }
+        }
+        for name in names.iter().filter(|name| !reached.contains(*name)) {
+            issues.push(Issue::Unreachable { room: name.to_string() });
+        }
+    }
+
+    for key in &names {
+        for exit in &rooms[*key].exits {
+            let Some(target) = rooms.get(&exit.room_name) else {
+                continue;
+            };
+            if !target.exits.iter().any(|back| back.room_name == **key) {
+                issues.push(Issue::OneWayExit {
+                    room: key.to_string(),
+                    direction: exit.direction.clone(),
+                    target: exit.room_name.clone(),
+                });
+            }
+        }
+    }
+
+    // Stable, so issues of the same severity keep the order found
+    issues.sort_by_key(Issue::severity);
+    ValidationReport { issues }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_util::world, Item, Npc};
+
+    #[test]
+    fn test_valid_world() {
+        let rooms = world(&[("Hall", true, &[("north", "Study")]), ("Study", false, &[("south", "Hall")])]);
+        assert!(validate(&rooms, Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn test_reports_every_problem() {
+        let mut rooms = world(&[
+            ("Hall", true, &[("north", "Study"), ("N", "Cellar"), ("east", "Garden")]),
+            ("Study", false, &[("south", "Hall")]),
+            ("Cellar", false, &[]),
+            ("Attic", false, &[("down", "Study")]),
+        ]);
+        rooms.get_mut("Study").unwrap().name = "Library".to_string();
+        let report = validate(&rooms, Vec::new());
+        let s = str::to_string;
+        assert_eq!(report.issues, [
+            Issue::DuplicateDirection { room: s("Hall"), direction: s("N") },
+            Issue::UnknownExit { room: s("Hall"), direction: s("east"), target: s("Garden") },
+            Issue::NameMismatch { key: s("Study"), name: s("Library") },
+            Issue::Unreachable { room: s("Attic") },
+            Issue::OneWayExit { room: s("Attic"), direction: s("down"), target: s("Study") },
+            Issue::OneWayExit { room: s("Hall"), direction: s("N"), target: s("Cellar") },
+        ]);
+        assert_eq!(report.errors().count(), 3);
+        assert!(report.to_string().starts_with("3 error(s), 3 warning(s)\n  error: room 'Hall' has more than one exit N"));
+    }
+
+    #[test]
+    fn test_aliases_clash_with_directions() {
+        let mut rooms = world(&[("Hall", true, &[("north", "Hall"), ("east", "Hall")])]);
+        rooms.get_mut("Hall").unwrap().exits[1].aliases = vec!["Gate".to_string(), "n".to_string()];
+        assert_eq!(validate(&rooms, Vec::new()).issues, [
+            Issue::DuplicateDirection { room: "Hall".to_string(), direction: "n".to_string() },
+        ]);
+    }
+
+    #[test]
+    fn test_doors() {
+        let mut rooms = world(&[
+            ("Hall", true, &[("north", "Study"), ("down", "Cellar"), ("east", "Yard")]),
+            ("Study", false, &[("south", "Hall")]),
+            ("Cellar", false, &[("up", "Hall")]),
+            ("Yard", false, &[("west", "Hall")]),
+        ]);
+        let door = |name: &str, state, key: Option<&str>| Some(Door { name: name.to_string(), id: None, state, key: key.map(str::to_string), closes_after: None });
+        let set = |rooms: &mut HashMap<String, Room>, room: &str, exit: usize, d| rooms.get_mut(room).unwrap().exits[exit].door = d;
+        set(&mut rooms, "Hall", 0, door("oak door", DoorState::Locked, Some("brass key")));
+        set(&mut rooms, "Study", 0, door("Oak Door", DoorState::Locked, Some("brass key")));
+        let key = Item { name: "Brass Key".to_string(), description: String::new(), properties: Default::default() };
+        rooms.get_mut("Hall").unwrap().items.push(key);
+        assert!(validate(&rooms, Vec::new()).is_empty());
+
+        set(&mut rooms, "Study", 0, door("Oak Door", DoorState::Closed, Some("brass key")));
+        set(&mut rooms, "Hall", 1, door("trapdoor", DoorState::Locked, None));
+        set(&mut rooms, "Hall", 2, door("gate", DoorState::Open, None));
+        set(&mut rooms, "Yard", 0, door("gate", DoorState::Open, None));
+        set(&mut rooms, "Cellar", 0, door("GATE", DoorState::Open, Some("gate key")));
+        let s = str::to_string;
+        assert_eq!(validate(&rooms, Vec::new()).issues, [
+            Issue::DoorMismatch { door: s("Hall/north") },
+            Issue::UnknownKey { door: s("GATE"), key: s("gate key") },
+            Issue::OneSidedDoor { room: s("Cellar"), direction: s("up"), door: s("GATE") },
+            Issue::LockedWithoutKey { door: s("trapdoor") },
+            Issue::OneSidedDoor { room: s("Hall"), direction: s("down"), door: s("trapdoor") },
+        ]);
+    }
+
+    #[test]
+    fn test_door_ids() {
+        let mut rooms = world(&[
+            ("Hall", true, &[("east", "Yard"), ("north", "Study")]),
+            ("Yard", false, &[("west", "Hall")]),
+            ("Study", false, &[("south", "Hall")]),
+        ]);
+        let gate = |id: Option<&str>| Some(Door { name: "gate".to_string(), id: id.map(str::to_string), state: DoorState::Closed, key: None, closes_after: None });
+        for (room, exit) in [("Hall", 0), ("Hall", 1), ("Yard", 0), ("Study", 0)] {
+            rooms.get_mut(room).unwrap().exits[exit].door = gate(None);
+        }
+        // Two doorways with a gate each are two doors
+        assert!(validate(&rooms, Vec::new()).is_empty());
+        let id = |rooms: &HashMap<String, Room>, room: &str, exit: usize| door_id(rooms, room, &rooms[room].exits[exit]);
+        assert_eq!(id(&rooms, "Yard", 0).as_deref(), Some("Hall/east"));
+        assert_eq!(id(&rooms, "Hall", 0).as_deref(), Some("Hall/east"));
+        assert_eq!(id(&rooms, "Study", 0).as_deref(), Some("Hall/north"));
+
+        // An explicit id ties together exits that don't lead to each other
+        rooms.get_mut("Hall").unwrap().exits[1].door = None;
+        rooms.get_mut("Yard").unwrap().exits[0].door = None;
+        rooms.get_mut("Hall").unwrap().exits[0].door = gate(Some("g"));
+        rooms.get_mut("Study").unwrap().exits[0].door = gate(Some("g"));
+        assert_eq!(validate(&rooms, Vec::new()).issues, [Issue::DoorNotReciprocal { door: "g".to_string() }]);
+
+        // And the way back picks up the id too
+        rooms.get_mut("Yard").unwrap().exits[0].door = gate(None);
+        assert_eq!(id(&rooms, "Yard", 0).as_deref(), Some("g"));
+        assert_eq!(validate(&rooms, Vec::new()).issues, [Issue::DoorReused { door: "g".to_string() }]);
+    }
+
+    #[test]
+    fn test_duplicate_npcs() {
+        let mut rooms = world(&[("Hall", true, &[("north", "Study")]), ("Study", false, &[("south", "Hall")])]);
+        let npc = |name: &str| Npc { name: name.to_string(), description: String::new(), wander: 0, greeting: None, responses: Default::default() };
+        rooms.get_mut("Hall").unwrap().npcs = vec![npc("Old Tom"), npc("Cat")];
+        rooms.get_mut("Study").unwrap().npcs = vec![npc("old tom")];
+        assert_eq!(validate(&rooms, Vec::new()).issues, [Issue::DuplicateNpc {
+            name: "old tom".to_string(),
+            first: "Hall".to_string(),
+            second: "Study".to_string(),
+        }]);
+    }
+
+    #[test]
+    fn test_missing_start_room() {
+        let rooms = world(&[("Hall", false, &[])]);
+        assert_eq!(validate(&rooms, Vec::new()).issues, [Issue::NoStartRoom]);
+    }
+
+    #[test]
+    fn test_one_way_exits_are_only_warnings() {
+        let rooms = world(&[("Hall", true, &[("down", "Pit")]), ("Pit", false, &[])]);
+        let report = validate(&rooms, Vec::new());
+        assert!(!report.has_errors());
+        assert_eq!(report.warnings().count(), 1);
+    }
+}
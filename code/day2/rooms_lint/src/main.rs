@@ -0,0 +1,64 @@
+use std::{path::PathBuf, process::ExitCode};
+use clap::Parser;
+use colored::Colorize;
+use rooms_library2::{Issue, RoomLibrary, Severity, ValidationReport, WorldFormat};
+
+/// Checks a world for mistakes before it goes to the server
+#[derive(Debug, Parser)]
+struct Args {
+    /// A rooms file, or a directory of zone files
+    #[arg(env = "MUD_ROOMS", default_value = "rooms.json")]
+    rooms: PathBuf,
+
+    /// Lowercase directions and add missing return exits, rewriting the files
+    /// that change. Comments in rewritten TOML, RON or YAML files are lost.
+    #[arg(long)]
+    fix: bool,
+}
+
+fn print_report(report: &ValidationReport) {
+    for issue in &report.issues {
+        match issue.severity() {
+            Severity::Error => println!("{} {}", "error:".red().bold(), issue),
+            Severity::Warning => println!("{} {}", "warning:".yellow().bold(), issue),
+        }
+    }
+    let errors = report.errors().count();
+    let warnings = report.warnings().count();
+    let summary = format!("{errors} error(s), {warnings} warning(s)");
+    if errors > 0 {
+        println!("{}", summary.red());
+    } else if warnings > 0 {
+        println!("{}", summary.yellow());
+    } else {
+        println!("{}", "No problems found".bright_green());
+    }
+}
+
+fn main() -> anyhow::Result<ExitCode> {
+    let args = Args::parse();
+
+    let mut world = RoomLibrary::check(&args.rooms)?;
+    if args.fix {
+        // Saving would drop the second copy of a duplicate room
+        if world.report.issues.iter().any(|issue| matches!(issue, Issue::DuplicateRoom { .. })) {
+            print_report(&world.report);
+            eprintln!("{}", "Not fixing anything until the duplicate rooms are resolved".red());
+            return Ok(ExitCode::FAILURE);
+        }
+        let fixes = rooms_library2::fix(&mut world.rooms);
+        if !fixes.is_empty() {
+            let written = world.save()?;
+            for fix in &fixes {
+                println!("{} {}", "fixed:".bright_green().bold(), fix);
+            }
+            for path in written.iter().filter(|path| WorldFormat::from_path(path).is_some_and(WorldFormat::has_comments)) {
+                println!("{} rewrote {}; any comments in it are gone", "note:".yellow().bold(), path.display());
+            }
+            world = RoomLibrary::check(&args.rooms)?;
+        }
+    }
+
+    print_report(&world.report);
+    Ok(if world.report.has_errors() { ExitCode::FAILURE } else { ExitCode::SUCCESS })
+}
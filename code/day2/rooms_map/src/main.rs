@@ -0,0 +1,48 @@
+use std::path::PathBuf;
+use clap::{Parser, ValueEnum};
+use colored::Colorize;
+use rooms_library2::RoomLibrary;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// A grid map for the terminal, following compass exits
+    Ascii,
+    /// Graphviz, e.g. `rooms_map --format dot | dot -Tsvg > world.svg`
+    Dot,
+}
+
+/// Draws a world as an ASCII map or a Graphviz graph
+#[derive(Debug, Parser)]
+struct Args {
+    /// A rooms file, or a directory of zone files
+    #[arg(env = "MUD_ROOMS", default_value = "rooms.json")]
+    rooms: PathBuf,
+
+    #[arg(long, value_enum, default_value = "ascii")]
+    format: Format,
+
+    /// File to write; stdout if omitted
+    #[arg(long, short)]
+    output: Option<PathBuf>,
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+
+    // Broken worlds are drawn anyway, since the map helps find the problem
+    let world = RoomLibrary::check(&args.rooms)?;
+    let errors = world.report.errors().count();
+    if errors > 0 {
+        eprintln!("{}", format!("The world has {errors} error(s); run rooms_lint for details").yellow());
+    }
+
+    let drawing = match args.format {
+        Format::Ascii => rooms_library2::to_ascii_map(&world.rooms),
+        Format::Dot => rooms_library2::to_dot(&world.rooms),
+    };
+    match args.output {
+        Some(path) => std::fs::write(path, drawing)?,
+        None => print!("{drawing}"),
+    }
+    Ok(())
+}
@@ -10,8 +10,11 @@ pub fn read_line() -> String {
 }
 
 fn main() -> anyhow::Result<()> {
-    // Load the rooms
-    let rooms = rooms_library2::RoomLibrary::load()?;
+    // Load the rooms, from a file or directory of zones if one is given
+    let rooms = match std::env::args().nth(1) {
+        Some(path) => rooms_library2::RoomLibrary::load_from(path)?,
+        None => rooms_library2::RoomLibrary::load()?,
+    };
 
     // Find a starting room
     let Some(start_room) = rooms.values().find(|room| room.start) else {
@@ -44,7 +47,7 @@ fn main() -> anyhow::Result<()> {
             println!("Exiting the game. Goodbye!");
             break;
         }
-        let exit = room.exits.iter().find(|e| e.direction.to_lowercase() == command);
+        let exit = room.find_exit(&command);
         if let Some(exit) = exit {
             current_room = exit.room_name.clone();
             println!("You move to the {} room.", exit.room_name.bright_yellow());
@@ -1,4 +1,4 @@
-use std::io::{Read, Write};
+use std::io::{IsTerminal, Read, Write};
 use std::net::TcpStream;
 use sync_mud_proto::MudMessage;
 
@@ -10,6 +10,17 @@ pub fn read_line() -> String {
     input.trim().to_string()
 }
 
+/// Reads a password without echoing it. If stdin isn't a terminal (input
+/// piped in from a script) there's nothing to hide, so read a plain line.
+pub fn read_password(prompt: &str) -> String {
+    if std::io::stdin().is_terminal() {
+        rpassword::prompt_password(format!("{prompt} ")).expect("Failed to read password")
+    } else {
+        println!("{prompt}");
+        read_line()
+    }
+}
+
 fn main() -> anyhow::Result<()> {
     // Connect to the server
     let mut stream = TcpStream::connect("localhost:8080")?;
@@ -22,8 +33,7 @@ fn main() -> anyhow::Result<()> {
     // Ask for username and password
     println!("Enter username:");
     let username = read_line();
-    println!("Enter password:");
-    let password = read_line();
+    let password = read_password("Enter password:");
 
     // Send login message
     let login_msg = MudMessage::Login { username, password };
@@ -94,7 +104,7 @@ fn main() -> anyhow::Result<()> {
                 }
                 
                 // Check if it's a valid exit
-                if room.exits.iter().any(|e| e.direction.to_lowercase() == command) {
+                if room.find_exit(&command).is_some() {
                     // Send TryExit message
                     let exit_msg = MudMessage::TryExit { direction: command };
                     let exit_bytes = exit_msg.to_bytes()?;
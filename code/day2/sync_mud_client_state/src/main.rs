@@ -106,7 +106,7 @@ fn main() -> anyhow::Result<()> {
             break;
         }
 
-        if current_room.as_ref().map(|r| r.exits.iter().any(|e| e.direction.to_lowercase() == command)).unwrap_or(false) {
+        if current_room.as_ref().is_some_and(|r| r.find_exit(&command).is_some()) {
             // Send TryExit message
             let exit_msg = MudMessage::TryExit { direction: command };
             let exit_bytes = exit_msg.to_bytes()?;
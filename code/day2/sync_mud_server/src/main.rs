@@ -58,7 +58,7 @@ fn handle_client(mut stream: std::net::TcpStream, rooms: Arc<HashMap<String, roo
         match msg {
             MudMessage::Login { username, password } => {
                 let users = login_library2::LoginManager::new()?;
-                if let Some(_user) = users.verify_user(&username, &password) {
+                if let Some(_user) = users.verify_user(&username, &password)? {
                     // Successful login
                     current_user = Some(username.clone());
                     tracing::info!("User {} logged in successfully", username);
@@ -91,7 +91,7 @@ fn handle_client(mut stream: std::net::TcpStream, rooms: Arc<HashMap<String, roo
                     anyhow::bail!("Client tried to exit without logging in");
                 }
                 let current_room_data = rooms.get(&current_room).ok_or_else(|| anyhow::anyhow!("Current room not found: {}", current_room))?;
-                if let Some(exit) = current_room_data.exits.iter().find(|e| e.direction == direction) {
+                if let Some(exit) = current_room_data.find_exit(&direction) {
                     if let Some(new_room) = rooms.get(&exit.room_name) {
                         current_room = new_room.name.clone();
                         let enter_msg = MudMessage::EnterRoom { room: new_room.clone() };
@@ -104,7 +104,7 @@ fn handle_client(
         match msg {
             MudMessage::Login { username, password } => {
                 let users = login_library2::LoginManager::new()?;
-                if let Some(_user) = users.verify_user(&username, &password) {
+                if let Some(_user) = users.verify_user(&username, &password)? {
                     // Successful login
                     *current_user = Some(username.clone());
                     tracing::info!("User {} logged in successfully", username);
@@ -141,7 +141,7 @@ fn handle_client(
                     anyhow::bail!("Client tried to exit without being logged in");
                 };
                 let current_room_data = rooms.get(&current_room).ok_or_else(|| anyhow::anyhow!("Current room not found: {}", current_room))?;
-                if let Some(exit) = current_room_data.exits.iter().find(|e| e.direction == direction) {
+                if let Some(exit) = current_room_data.find_exit(&direction) {
                     if let Some(new_room) = rooms.get(&exit.room_name) {
                         // Move user to new room
                         WORLD_STATE.remove_user_from_room(username, &current_room);
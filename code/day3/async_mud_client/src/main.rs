@@ -1,5 +1,5 @@
-use std::{io::Write, sync::mpsc::{Receiver, Sender}};
-use async_mud_proto::{sync_messaging::{send_message, read_message}, MudMessage};
+use std::{io::{IsTerminal, Write}, net::TcpStream, sync::mpsc::Sender, time::Duration};
+use async_mud_proto::{sync_messaging::{send_message, read_message}, DoorAction, MudMessage};
 use colored::Colorize;
 
 pub fn read_line() -> String {
@@ -10,85 +10,210 @@ pub fn read_line() -> String {
     input.trim().to_string()
 }
 
-fn send_magic_number(socket: &mut std::net::TcpStream) -> anyhow::Result<()> {
+/// Reads a password without echoing it. If stdin isn't a terminal (input
+/// piped in from a script) there's nothing to hide, so read a plain line.
+pub fn read_password(prompt: &str) -> String {
+    if std::io::stdin().is_terminal() {
+        rpassword::prompt_password(format!("{} ", prompt.yellow())).expect("Failed to read password")
+    } else {
+        println!("{}", prompt.yellow());
+        read_line()
+    }
+}
+
+fn send_magic_number(socket: &mut TcpStream) -> anyhow::Result<()> {
     socket.write_all(&[0x4D, 0x55, 0x44, 0x31])?; // 'MUD1'
     Ok(())
 }
 
-fn main() -> anyhow::Result<()> {
-    println!("{}", "MUD Client".green());
-
-    // Obtain credentials
-    println!("{}", "Enter your username:".yellow());
-    let username = read_line();
+/// How many times to try getting back into the game after the connection drops
+const RECONNECT_ATTEMPTS: u32 = 8;
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(10);
 
-    println!("{}", "Enter your password:".yellow());
-    let password = read_line();
-
-    // Connect and login
-    println!("{}", "Connecting to server...".yellow());
-    let mut socket = std::net::TcpStream::connect("127.0.0.1:8080")?;
+/// Things the main thread reacts to.
+enum ClientEvent {
+    /// Typed by the user, to be sent to the server
+    Outgoing(MudMessage),
+    /// The receive thread for the given connection lost its socket
+    ConnectionLost(u64),
+    /// The server accepted our last ChangePassword
+    PasswordChanged,
+    /// The server closed the session on purpose, so don't reconnect
+    Disconnected,
+}
 
+/// Connects, sends the given Login, Resume or Register message, and waits for the verdict.
+/// The outer error is a network problem; the inner one is the server saying no.
+///
+/// If the account has two-factor authentication, the user is asked for a code,
+/// unless we're not `interactive` (the input thread owns stdin).
+fn connect_and_login(login_msg: &MudMessage, interactive: bool) -> anyhow::Result<Result<(TcpStream, String), String>> {
+    let mut socket = TcpStream::connect("127.0.0.1:8080")?;
     send_magic_number(&mut socket)?;
-    println!("{}", "Connected. Sending login...".yellow());
-    let login_msg = MudMessage::Login { username, password };
-    send_message(&mut socket, &login_msg)?;
-    let Ok(MudMessage::LoginSuccess) = read_message(&mut socket) else {
-        println!("{}", "Login failed.".red());
-        return Ok(());
-    };
-    println!("{}", "Login successful!".green());
+    send_message(&mut socket, login_msg)?;
+    loop {
+        match read_message(&mut socket)? {
+            MudMessage::LoginSuccess { session_token } => return Ok(Ok((socket, session_token))),
+            MudMessage::LoginFail { reason } => return Ok(Err(reason.to_string())),
+            MudMessage::RegisterFail { reason } => return Ok(Err(reason)),
+            MudMessage::TotpChallenge if interactive => {
+                println!("{}", "Enter the code from your authenticator app:".yellow());
+                send_message(&mut socket, &MudMessage::TotpResponse { code: read_line() })?;
+            }
+            MudMessage::TotpChallenge => {
+                return Ok(Err("this account needs a one-time code, please log in again".to_string()));
+            }
+            other => anyhow::bail!("Unexpected reply to login: {:?}", other),
+        }
+    }
+}
 
-    // Synchronous TCP stream doesn't need splitting, it can be cloned.
+/// Asks for a new password twice, until both entries match.
+fn read_new_password(prompt: &str) -> String {
+    loop {
+        let password = read_password(prompt);
+        if read_password("Confirm the password:") == password {
+            return password;
+        }
+        println!("{}", "Passwords do not match, try again.".red());
+    }
+}
 
-    // Make the channel
-    let (tcp_tx, tcp_rx) = std::sync::mpsc::channel();
-    
-    // Start the message sending thread
-    let send_thread = std::thread::spawn({
-        let socket = socket.try_clone()?;
-        move || message_send_loop(tcp_rx, socket)
-    });
+/// Gets back into the game after a dropped connection: first by resuming the
+/// session, falling back to logging in again if the session has expired.
+fn reconnect(session_token: &str, username: &str, password: &str) -> anyhow::Result<(TcpStream, String)> {
+    let mut delay = Duration::from_secs(1);
+    for _ in 0..RECONNECT_ATTEMPTS {
+        std::thread::sleep(delay);
+        println!("{}", "Reconnecting...".yellow());
+        let resume_msg = MudMessage::Resume { session_token: session_token.to_string() };
+        match connect_and_login(&resume_msg, false) {
+            Ok(Ok(session)) => return Ok(session),
+            Ok(Err(reason)) => {
+                println!("{}", format!("Could not resume: {}.", reason).yellow());
+                let login_msg = MudMessage::Login { username: username.to_string(), password: password.to_string() };
+                return match connect_and_login(&login_msg, false)? {
+                    Ok(session) => Ok(session),
+                    Err(reason) => anyhow::bail!("Login failed: {}", reason),
+                };
+            }
+            Err(_) => delay = (delay * 2).min(MAX_RECONNECT_DELAY),
+        }
+    }
+    anyhow::bail!("Gave up reconnecting after {} attempts", RECONNECT_ATTEMPTS)
+}
 
-    // Start the message receiving thread
-    let recv_thread = std::thread::spawn({
-        let socket = socket.try_clone()?;
-        move || message_receive_loop(socket)
+/// Synchronous TCP stream doesn't need splitting, it can be cloned.
+fn spawn_receiver(socket: &TcpStream, connection: u64, events: Sender<ClientEvent>) -> anyhow::Result<()> {
+    let socket = socket.try_clone()?;
+    std::thread::spawn(move || {
+        let _ = message_receive_loop(socket, &events);
+        let _ = events.send(ClientEvent::ConnectionLost(connection));
     });
+    Ok(())
+}
 
-    // Start the user input thread
-    let input_thread = std::thread::spawn(|| user_input_loop(tcp_tx));
+fn main() -> anyhow::Result<()> {
+    println!("{}", "MUD Client".green());
 
-    let _ = send_thread.join();
-    let _ = recv_thread.join();
-    let _ = input_thread.join();
+    // Obtain credentials, or details for a new account
+    println!("{}", "Enter your username (or \"new\" to create an account):".yellow());
+    let mut username = read_line();
+    let registering = username.eq_ignore_ascii_case("new");
+    let mut password = if registering {
+        println!("{}", "Choose a username:".yellow());
+        username = read_line();
+        read_new_password("Choose a password:")
+    } else {
+        read_password("Enter your password:")
+    };
+    let login_msg = if registering {
+        MudMessage::Register { username: username.clone(), password: password.clone() }
+    } else {
+        MudMessage::Login { username: username.clone(), password: password.clone() }
+    };
 
-    Ok(())
-}
+    // Connect and login
+    println!("{}", "Connecting to server...".yellow());
+    let (mut socket, mut session_token) = match connect_and_login(&login_msg, true)? {
+        Ok(session) => session,
+        Err(reason) => {
+            let action = if registering { "Registration" } else { "Login" };
+            println!("{}", format!("{} failed: {}.", action, reason).red());
+            return Ok(());
+        }
+    };
+    println!("{}", "Login successful!".green());
 
-fn message_send_loop(
-    rx: Receiver<MudMessage>,
-    mut socket: std::net::TcpStream,
-) -> anyhow::Result<()> {
-    while let Ok(msg) = rx.recv() {
-        send_message(&mut socket, &msg)?;
+    // Make the channel, and start the receiving and user input threads
+    let (event_tx, event_rx) = std::sync::mpsc::channel();
+    let receiver_tx = event_tx.clone();
+    let mut connection = 0;
+    spawn_receiver(&socket, connection, receiver_tx.clone())?;
+    let input_thread = std::thread::spawn(move || user_input_loop(event_tx));
+
+    // The main thread sends messages, and reconnects if the connection drops.
+    // A changed password is remembered for logging in again after a reconnect.
+    let mut pending_password = None;
+    while let Ok(event) = event_rx.recv() {
+        let lost = match event {
+            ClientEvent::Outgoing(msg) => {
+                let sent = send_message(&mut socket, &msg);
+                match msg {
+                    MudMessage::Disconnect => break,
+                    MudMessage::ChangePassword { new_password, .. } => pending_password = Some(new_password),
+                    _ => {}
+                }
+                sent.is_err()
+            }
+            // Ignore late notices from connections we've already replaced
+            ClientEvent::ConnectionLost(lost) => lost == connection,
+            ClientEvent::PasswordChanged => {
+                if let Some(new_password) = pending_password.take() {
+                    password = new_password;
+                }
+                false
+            }
+            // The input thread is waiting on stdin, so don't wait for it
+            ClientEvent::Disconnected => return Ok(()),
+        };
+        if lost {
+            println!("{}", "Connection lost.".red());
+            (socket, session_token) = reconnect(&session_token, &username, &password)?;
+            connection += 1;
+            spawn_receiver(&socket, connection, receiver_tx.clone())?;
+        }
     }
+
+    let _ = input_thread.join();
     Ok(())
 }
 
 fn message_receive_loop(
-    mut socket: std::net::TcpStream,
+    mut socket: TcpStream,
+    events: &Sender<ClientEvent>,
 ) -> anyhow::Result<()> {
     loop {
         let msg = read_message(&mut socket)?;
         match msg {
-            MudMessage::EnterRoom { room, other_players } => {
-                println!("{}", format!("{}", room.name).green());
-                println!("{}", format!("{}", room.description).white());
-                println!("{}", format!("Exits: {}", room.exits.iter().map(|exit| exit.direction.clone()).collect::<Vec<String>>().join(", ")).blue());
+            MudMessage::EnterRoom { room, other_players, npcs } => {
+                println!("{}", room.name.green());
+                println!("{}", room.description.white());
+                let exits: Vec<String> = room.exits.iter().map(|exit| match &exit.door {
+                    Some(door) => format!("{} ({} {})", exit.direction, door.state, door.name),
+                    None => exit.direction.clone(),
+                }).collect();
+                println!("{}", format!("Exits: {}", exits.join(", ")).blue());
+                if !room.items.is_empty() {
+                    let items: Vec<&str> = room.items.iter().map(|item| item.name.as_str()).collect();
+                    println!("{}", format!("You see: {}", items.join(", ")).bright_blue());
+                }
+                if !npcs.is_empty() {
+                    println!("{}", format!("Also here: {}", npcs.join(", ")).magenta());
+                }
                 if !other_players.is_empty() {
                     println!("{}", format!("Other players here: {}", other_players.join(", ")).magenta());
-                } else {
+                } else if npcs.is_empty() {
                     println!("{}", "You are alone here.".magenta());
                 }
             }
@@ -101,19 +226,72 @@ fn message_receive_loop(
             MudMessage::PlayerSpeak { username, message } => {
                 println!("{}", format!("{} says: {}", username, message).yellow());
             }
+            MudMessage::PasswordChanged => {
+                println!("{}", "Password changed.".green());
+                events.send(ClientEvent::PasswordChanged)?;
+            }
+            MudMessage::PasswordChangeFail { reason } => {
+                println!("{}", format!("Password not changed: {}.", reason).red());
+            }
+            MudMessage::Notice { message } => {
+                println!("{}", message.yellow());
+            }
+            MudMessage::Disconnect => {
+                println!("{}", "Disconnected by the server.".red());
+                events.send(ClientEvent::Disconnected)?;
+                return Ok(());
+            }
+            MudMessage::DoorChanged { door, action, username } => {
+                let who = username.unwrap_or_else(|| "Someone on the other side".to_string());
+                println!("{}", format!("{} {}s the {}.", who, action.verb(), door).cyan());
+            }
+            MudMessage::InventoryList { items } => {
+                if items.is_empty() {
+                    println!("{}", "You aren't carrying anything.".bright_blue());
+                } else {
+                    let items: Vec<&str> = items.iter().map(|item| item.name.as_str()).collect();
+                    println!("{}", format!("You are carrying: {}", items.join(", ")).bright_blue());
+                }
+            }
+            MudMessage::ItemDetails { item } => {
+                println!("{}", item.name.green());
+                if !item.description.is_empty() {
+                    println!("{}", item.description.white());
+                }
+                for (key, value) in &item.properties {
+                    println!("{}", format!("  {key}: {value}").dimmed());
+                }
+            }
+            MudMessage::NpcEnteredRoom { name } => {
+                println!("{}", format!("{} arrives.", name).cyan());
+            }
+            MudMessage::NpcLeftRoom { name, direction } => {
+                println!("{}", format!("{} leaves, heading {}.", name, direction).cyan());
+            }
+            MudMessage::NpcSpeak { name, message } => {
+                println!("{}", format!("{} says: {}", name, message).yellow());
+            }
+            MudMessage::PlayerGotItem { username, item } => {
+                println!("{}", format!("{} picks up the {}.", username, item).cyan());
+            }
+            MudMessage::PlayerDroppedItem { username, item } => {
+                println!("{}", format!("{} drops the {}.", username, item).cyan());
+            }
+            MudMessage::PlayerGaveItem { username, item, recipient } => {
+                println!("{}", format!("{} gives the {} to {}.", username, item, recipient).cyan());
+            }
             _ => {}
         }
     }
-    Ok(())
 }
 
 fn user_input_loop(
-    tcp_tx: Sender<MudMessage>,
+    tcp_tx: Sender<ClientEvent>,
 ) -> anyhow::Result<()> {
     loop {
         let input = read_line();
         if input.eq_ignore_ascii_case("quit") || input.eq_ignore_ascii_case("exit") {
-            tcp_tx.send(MudMessage::Disconnect)?;
+            tcp_tx.send(ClientEvent::Outgoing(MudMessage::Disconnect))?;
             println!("{}", "Disconnecting...".yellow());
             break;
         }
@@ -121,14 +299,58 @@ fn user_input_loop(
         if input.to_lowercase().starts_with("say ") {
             let message = input[4..].trim().to_string();
             if !message.is_empty() {
-                tcp_tx.send(MudMessage::PlayerSpeak { username: "".to_string(), message })?;
+                tcp_tx.send(ClientEvent::Outgoing(MudMessage::PlayerSpeak { username: "".to_string(), message }))?;
             } else {
                 println!("{}", "Cannot send empty message.".red());
             }
             continue;
         }
 
-        tcp_tx.send(MudMessage::TryExit { direction :input })?;
+        if input.eq_ignore_ascii_case("password") {
+            let old_password = read_password("Enter your current password:");
+            let new_password = read_new_password("Enter your new password:");
+            tcp_tx.send(ClientEvent::Outgoing(MudMessage::ChangePassword { old_password, new_password }))?;
+            continue;
+        }
+
+        if input.eq_ignore_ascii_case("reload") {
+            tcp_tx.send(ClientEvent::Outgoing(MudMessage::ReloadWorld))?;
+            continue;
+        }
+
+        // "open", "unlock north", "close oak door" and so on
+        let (word, target) = input.split_once(' ').unwrap_or((&input, ""));
+        let target = target.trim().to_string();
+        if let Some(action) = DoorAction::ALL.into_iter().find(|a| a.verb().eq_ignore_ascii_case(word)) {
+            tcp_tx.send(ClientEvent::Outgoing(MudMessage::OperateDoor { action, target }))?;
+            continue;
+        }
+
+        let item_command = match word.to_lowercase().as_str() {
+            "inventory" | "inv" | "i" => Some(MudMessage::Inventory),
+            "get" | "take" | "drop" | "examine" | "x" if target.is_empty() => {
+                let verb = if word.eq_ignore_ascii_case("x") { "examine".to_string() } else { word.to_lowercase() };
+                println!("{}", format!("What do you want to {verb}?").red());
+                continue;
+            }
+            "get" | "take" => Some(MudMessage::GetItem { item: target }),
+            "drop" => Some(MudMessage::DropItem { item: target }),
+            "examine" | "x" => Some(MudMessage::Examine { item: target }),
+            "give" => {
+                let Some((item, username)) = target.rsplit_once(" to ") else {
+                    println!("{}", "Give what to whom? e.g. give lamp to bob".red());
+                    continue;
+                };
+                Some(MudMessage::GiveItem { item: item.trim().to_string(), username: username.trim().to_string() })
+            }
+            _ => None,
+        };
+        if let Some(msg) = item_command {
+            tcp_tx.send(ClientEvent::Outgoing(msg))?;
+            continue;
+        }
+
+        tcp_tx.send(ClientEvent::Outgoing(MudMessage::TryExit { direction :input }))?;
     }
     Ok(())   
 }
\ No newline at end of file
@@ -1,19 +1,109 @@
-use rooms_library2::Room;
+use rooms_library2::{Item, Room};
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub enum MudMessage {
     Login { username: String, password: String },
-    LoginSuccess,
-    LoginFail,
-    EnterRoom { room: Room, other_players: Vec<String> },
+    /// Sent instead of Login to pick up a dropped session
+    Resume { session_token: String },
+    /// Sent instead of Login to create a new account and log straight into it
+    Register { username: String, password: String },
+    /// The password was right but the account also needs a one-time code
+    TotpChallenge,
+    TotpResponse { code: String },
+    LoginSuccess { session_token: String },
+    LoginFail { reason: LoginFailReason },
+    /// Registration was refused; the reason is shown to the user
+    RegisterFail { reason: String },
+    EnterRoom { room: Room, other_players: Vec<String>, npcs: Vec<String> },
     TryExit { direction: String },
     Disconnect,
+    ChangePassword { old_password: String, new_password: String },
+    PasswordChanged,
+    PasswordChangeFail { reason: String },
+    /// Asks the server to reload the world from disk; builders and admins only
+    ReloadWorld,
+    /// Something for the player to read, e.g. that a reload moved them
+    Notice { message: String },
+    /// Opens, closes, locks or unlocks a door, named or by the exit it's on;
+    /// an empty target means the only door in the room
+    OperateDoor { action: DoorAction, target: String },
+    /// Someone operated a door in this room. `username` is None when it was
+    /// done from the other side.
+    DoorChanged { door: String, action: DoorAction, username: Option<String> },
+    GetItem { item: String },
+    DropItem { item: String },
+    /// Gives an item to another player in the same room
+    GiveItem { item: String, username: String },
+    /// Asks for an item's details, whether it's carried or in the room
+    Examine { item: String },
+    /// Asks what the player is carrying
+    Inventory,
+    InventoryList { items: Vec<Item> },
+    ItemDetails { item: Item },
 
     Ping,
     PlayerEnteredRoom { username: String },
     PlayerLeftRoom { username: String, direction: String },
     PlayerSpeak { username: String, message: String },
+    PlayerGotItem { username: String, item: String },
+    PlayerDroppedItem { username: String, item: String },
+    PlayerGaveItem { username: String, item: String, recipient: String },
+    NpcEnteredRoom { name: String },
+    NpcLeftRoom { name: String, direction: String },
+    NpcSpeak { name: String, message: String },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DoorAction {
+    Open,
+    Close,
+    Lock,
+    Unlock,
+}
+
+impl DoorAction {
+    pub const ALL: [DoorAction; 4] = [DoorAction::Open, DoorAction::Close, DoorAction::Lock, DoorAction::Unlock];
+
+    /// The command word, e.g. "open".
+    pub fn verb(self) -> &'static str {
+        match self {
+            DoorAction::Open => "open",
+            DoorAction::Close => "close",
+            DoorAction::Lock => "lock",
+            DoorAction::Unlock => "unlock",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoginFailReason {
+    /// Unknown username or wrong password
+    BadCredentials,
+    /// Too many recent failures; wait before trying again
+    Throttled { retry_after_secs: u64 },
+    /// Too many failures; the account or address is locked for a while
+    LockedOut { retry_after_secs: u64 },
+    /// The session token presented with Resume is unknown or has expired
+    SessionExpired,
+    /// The one-time code sent in answer to TotpChallenge was wrong
+    BadTotpCode,
+}
+
+impl std::fmt::Display for LoginFailReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoginFailReason::BadCredentials => write!(f, "bad username or password"),
+            LoginFailReason::Throttled { retry_after_secs } => {
+                write!(f, "too many failed attempts, try again in {retry_after_secs}s")
+            }
+            LoginFailReason::LockedOut { retry_after_secs } => {
+                write!(f, "locked out after repeated failures, try again in {retry_after_secs}s")
+            }
+            LoginFailReason::SessionExpired => write!(f, "session expired, please log in again"),
+            LoginFailReason::BadTotpCode => write!(f, "invalid one-time code"),
+        }
+    }
 }
 
 impl MudMessage {
@@ -28,7 +118,6 @@ impl MudMessage {
     }
 }
 
-#[cfg(not(feature = "tokio"))]
 pub mod sync_messaging {
     use super::MudMessage;
     use std::io::{Read, Write};
@@ -1,18 +1,102 @@
+mod sessions;
+mod ticks;
 mod world_manager;
-use async_mud_proto::{async_messaging::{read_message, send_message}, MudMessage};
-use login_library2::User;
-use tokio::{io::AsyncReadExt, select, sync::mpsc::{Receiver, Sender}};
+use std::{path::PathBuf, sync::atomic::{AtomicBool, AtomicU64, Ordering}};
+use async_mud_proto::{async_messaging::{read_message, send_message}, LoginFailReason, MudMessage};
+use clap::Parser;
+use rooms_library2::PlayerStoreConfig;
+use login_library2::{AccountPolicy, Attempt, AuditAction, AuditEvent, AuditLog, LockoutPolicy, LoginBlock, LoginError, LoginManager, LoginThrottle, Role, StoreConfig, User};
+use tokio::{io::AsyncReadExt, select, sync::{mpsc::{Receiver, Sender}, Mutex, OnceCell}};
+
+static LOGINS: OnceCell<LoginManager> = OnceCell::const_new();
+static THROTTLE: OnceCell<Mutex<LoginThrottle>> = OnceCell::const_new();
+static AUDIT: OnceCell<AuditLog> = OnceCell::const_new();
+static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(1);
+static REGISTRATION_OPEN: AtomicBool = AtomicBool::new(true);
+
+/// How often changes to the login throttle are written out. A crash loses
+/// at most this much of the failure log.
+const THROTTLE_SAVE_EVERY: std::time::Duration = std::time::Duration::from_secs(1);
+
+#[derive(Debug, Parser)]
+struct Args {
+    /// Where users are stored: `json:PATH`, `sqlite:PATH`, or a path (`.db` selects SQLite)
+    #[arg(long, env = "MUD_USERS", default_value = "users.json")]
+    users: StoreConfig,
+
+    /// Where failed login attempts are recorded, so lockouts survive a restart
+    #[arg(long, env = "MUD_LOGIN_ATTEMPTS", default_value = "login_attempts.json")]
+    login_attempts: PathBuf,
+
+    /// Append-only log of logins and account changes, shared with `login_cli2`
+    #[arg(long, env = "MUD_AUDIT_LOG", default_value = "audit.jsonl")]
+    audit_log: PathBuf,
+
+    /// JSON file of username and password rules; built-in defaults if omitted
+    #[arg(long, env = "MUD_ACCOUNT_POLICY")]
+    policy: Option<PathBuf>,
+
+    /// A rooms file, or a directory of zone files to merge into one world
+    #[arg(long, env = "MUD_ROOMS", default_value = "rooms.json")]
+    rooms: PathBuf,
+
+    /// Where players' progress is kept between sessions: `memory`, `json:PATH`, or a path
+    #[arg(long, env = "MUD_PLAYERS", default_value = "players.json")]
+    players: PlayerStoreConfig,
+
+    /// Seconds between saves of everyone's progress, on top of saving when
+    /// they leave; 0 only saves when they leave
+    #[arg(long, env = "MUD_SAVE_INTERVAL", default_value_t = 60)]
+    save_interval: u64,
+
+    /// How many times a second the world moves on by itself, e.g. NPCs
+    /// wandering and doors swinging shut
+    #[arg(long, env = "MUD_TICK_RATE", default_value_t = 10, value_parser = clap::value_parser!(u32).range(1..=1000))]
+    tick_rate: u32,
+
+    /// Don't reload the world when the rooms files change; builders and
+    /// admins can still reload it with the in-game `reload` command
+    #[arg(long, env = "MUD_NO_WATCH_ROOMS")]
+    no_watch_rooms: bool,
+
+    /// Refuse to create accounts from the login prompt; only `login_cli2 add` can
+    #[arg(long, env = "MUD_DISABLE_REGISTRATION")]
+    disable_registration: bool,
+}
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+
     // Setup Logging
     tracing_subscriber::fmt()
         .with_env_filter(tracing_subscriber::filter::EnvFilter::from_default_env())
         .compact()
         .init();
 
+    // Open the user store
+    tracing::info!("Using user store {:?}", args.users);
+    let mut logins = LoginManager::open(&args.users)?;
+    if let Some(path) = &args.policy {
+        tracing::info!("Using account policy from {}", path.display());
+        logins.set_policy(AccountPolicy::load(path)?);
+    }
+    LOGINS.set(logins)
+        .map_err(|_| anyhow::anyhow!("User store already initialized"))?;
+    let throttle = LoginThrottle::open(&args.login_attempts, LockoutPolicy::per_user(), LockoutPolicy::per_address())?;
+    THROTTLE.set(Mutex::new(throttle))
+        .map_err(|_| anyhow::anyhow!("Login throttle already initialized"))?;
+    tokio::spawn(save_throttle());
+    AUDIT.set(AuditLog::new(&args.audit_log))
+        .map_err(|_| anyhow::anyhow!("Audit log already initialized"))?;
+    REGISTRATION_OPEN.store(!args.disable_registration, Ordering::Relaxed);
+    tracing::info!("In-game registration is {}", if args.disable_registration { "disabled" } else { "enabled" });
+
     // Setup the World Manager
-    world_manager::run()?;
+    tracing::info!("Using player store {:?}", args.players);
+    let save_interval = (args.save_interval > 0).then(|| std::time::Duration::from_secs(args.save_interval));
+    let tick = std::time::Duration::from_secs(1) / args.tick_rate;
+    world_manager::run(&args.rooms, !args.no_watch_rooms, &args.players, save_interval, tick)?;
 
     // Start the server
     let listener = tokio::net::TcpListener::bind("127.0.0.1:8080").await?;
@@ -40,51 +124,284 @@ async fn check_magic_number(socket: &mut tokio::net::TcpStream) -> anyhow::Resul
     }
 }
 
-async fn handle_login(socket: &mut tokio::net::TcpStream) -> anyhow::Result<User> {
-    let MudMessage::Login { username, password } = read_message(socket).await? else {
-        anyhow::bail!("Expected Login message");
+/// Returns the logged-in user and their new session token.
+async fn handle_login(socket: &mut tokio::net::TcpStream, addr: std::net::SocketAddr) -> anyhow::Result<(User, String)> {
+    let user = match read_message(socket).await? {
+        MudMessage::Login { username, password } => password_login(socket, addr, username, password).await?,
+        MudMessage::Resume { session_token } => resume_login(socket, addr, &session_token).await?,
+        MudMessage::Register { username, password } => register(socket, addr, username, password).await?,
+        _ => anyhow::bail!("Expected Login, Resume or Register message"),
+    };
+    let session_token = sessions::issue(&user.username);
+    send_message(socket, &MudMessage::LoginSuccess { session_token: session_token.clone() }).await?;
+    Ok((user, session_token))
+}
+
+async fn resume_login(socket: &mut tokio::net::TcpStream, addr: std::net::SocketAddr, session_token: &str) -> anyhow::Result<User> {
+    let Some(username) = sessions::redeem(session_token) else {
+        send_message(socket, &MudMessage::LoginFail { reason: LoginFailReason::SessionExpired }).await?;
+        anyhow::bail!("Invalid or expired session token from {}", addr);
     };
-    tracing::info!("Login attempt for user {}", username);
-    let logins = login_library2::LoginManager::new()?;
-    let Some(user)  = logins.verify_user(&username, &password) else {
-        send_message(socket, &MudMessage::LoginFail).await?;
-        anyhow::bail!("Login failed for user {}", username);
+    // Load them again, so roles granted or revoked since they logged in apply
+    let name = username.clone();
+    let Some(user) = with_logins(move |logins| logins.user(&name)).await?? else {
+        send_message(socket, &MudMessage::LoginFail { reason: LoginFailReason::SessionExpired }).await?;
+        anyhow::bail!("User {} resuming from {} no longer exists", username, addr);
     };
-    send_message(socket, &MudMessage::LoginSuccess).await?;
+    tracing::info!("User {} resumed their session from {}", user.username, addr);
+    Ok(user)
+}
+
+async fn register(
+    socket: &mut tokio::net::TcpStream,
+    addr: std::net::SocketAddr,
+    username: String,
+    password: String,
+) -> anyhow::Result<User> {
+    tracing::info!("Registration attempt for user {} from {}", username, addr);
+    if !REGISTRATION_OPEN.load(Ordering::Relaxed) {
+        let reason = "registration is disabled on this server".to_string();
+        send_message(socket, &MudMessage::RegisterFail { reason }).await?;
+        anyhow::bail!("Registration refused for {} from {}: disabled", username, addr);
+    }
+
+    // An address that is locked out of logging in can't create accounts either,
+    // and one address can only create so many, counting those in progress
+    let throttle = THROTTLE.get().ok_or_else(|| anyhow::anyhow!("Login throttle not initialized"))?;
+    let now = login_library2::unix_now();
+    let attempt = throttle.lock().await.begin_registration(&username, addr.ip(), now);
+    let attempt = match attempt {
+        Ok(attempt) => attempt,
+        Err(block) => {
+        let reason = format!("too many attempts, try again in {}s", block.retry_after_secs());
+        audit(AuditEvent::new(&username, AuditAction::RegistrationRefused { reason: reason.clone() }).source(addr));
+            send_message(socket, &MudMessage::RegisterFail { reason }).await?;
+            anyhow::bail!("Registration refused for {} from {}: throttled", username, addr);
+        }
+    };
+
+    let name = username.clone();
+    let (result, attempt) = or_abandon(throttle, attempt, with_logins(move |logins| logins.add_user(&name, &password)).await).await?;
+    match result {
+        Ok(user) => {
+            tracing::info!("User {} registered from {}", username, addr);
+            if throttle.lock().await.registered(attempt, now) {
+                tracing::warn!("Address {} has registered too many accounts; refusing more for now", addr.ip());
+            }
+            let name = username.clone();
+            with_logins(move |logins| logins.record_login(&name, now, Some(addr.to_string()))).await??;
+            audit(AuditEvent::new(&username, AuditAction::UserAdded).source(addr));
+            Ok(user)
+        }
+        // Counted as failures, so probing for taken usernames gets throttled like guessing passwords
+        Err(e @ (LoginError::DuplicateUser(_) | LoginError::InvalidUsername(_) | LoginError::PasswordPolicyViolation(_))) => {
+            record_failure(throttle, attempt, &username, addr, now, AuditAction::RegistrationRefused { reason: e.to_string() }).await;
+            send_message(socket, &MudMessage::RegisterFail { reason: e.to_string() }).await?;
+            anyhow::bail!("Registration refused for {} from {}: {}", username, addr, e);
+        }
+        Err(e) => {
+            throttle.lock().await.abandon(attempt);
+            Err(e.into())
+        }
+    }
+}
+
+async fn password_login(
+    socket: &mut tokio::net::TcpStream,
+    addr: std::net::SocketAddr,
+    username: String,
+    password: String,
+) -> anyhow::Result<User> {
+    tracing::info!("Login attempt for user {} from {}", username, addr);
+    let throttle = THROTTLE.get().ok_or_else(|| anyhow::anyhow!("Login throttle not initialized"))?;
+
+    // Refuse without checking the password if there have been too many failures
+    let now = login_library2::unix_now();
+    let attempt = throttle.lock().await.begin(&username, addr.ip(), now);
+    let attempt = match attempt {
+        Ok(attempt) => attempt,
+        Err(block) => {
+            let reason = match block {
+                LoginBlock::Throttled { retry_after_secs } => LoginFailReason::Throttled { retry_after_secs },
+                LoginBlock::LockedOut { retry_after_secs } => LoginFailReason::LockedOut { retry_after_secs },
+            };
+            audit(AuditEvent::new(&username, AuditAction::LoginFailed { reason: reason.to_string() }).source(addr));
+            send_message(socket, &MudMessage::LoginFail { reason }).await?;
+            anyhow::bail!("Login refused for user {} from {}: {}", username, addr, reason);
+        }
+    };
+
+    let name = username.clone();
+    let verified = with_logins(move |logins| logins.verify_user(&name, &password)).await.and_then(|r| Ok(r?));
+    let (verified, mut attempt) = or_abandon(throttle, attempt, verified).await?;
+    let Some(user) = verified else {
+        record_failed_login(throttle, attempt, &username, addr, now, LoginFailReason::BadCredentials).await;
+        send_message(socket, &MudMessage::LoginFail { reason: LoginFailReason::BadCredentials }).await?;
+        anyhow::bail!("Login failed for user {} from {}", username, addr);
+    };
+
+    // Accounts with two-factor authentication need a one-time code as well
+    if user.requires_totp() {
+        send_message(socket, &MudMessage::TotpChallenge).await?;
+        // Hanging up or answering with anything else counts as a wrong code, so
+        // a client can't probe passwords by never finishing the challenge
+        let code = match read_message(socket).await {
+            Ok(MudMessage::TotpResponse { code }) => code,
+            reply => {
+                record_failed_login(throttle, attempt, &username, addr, now, LoginFailReason::BadTotpCode).await;
+                reply?;
+                anyhow::bail!("Expected TotpResponse message from {}", addr);
+            }
+        };
+        let name = username.clone();
+        let verified = with_logins(move |logins| logins.verify_totp(&name, &code, login_library2::unix_now())).await.and_then(|r| Ok(r?));
+        let (verified, returned) = or_abandon(throttle, attempt, verified).await?;
+        attempt = returned;
+        if !verified {
+            record_failed_login(throttle, attempt, &username, addr, now, LoginFailReason::BadTotpCode).await;
+            send_message(socket, &MudMessage::LoginFail { reason: LoginFailReason::BadTotpCode }).await?;
+            anyhow::bail!("Bad one-time code for user {} from {}", username, addr);
+        }
+    }
+    throttle.lock().await.succeeded(attempt);
+    let name = username.clone();
+    with_logins(move |logins| logins.record_login(&name, now, Some(addr.to_string()))).await??;
     tracing::info!("User {} logged in successfully", username);
-    Ok(user.clone())
+    audit(AuditEvent::new(&username, AuditAction::LoginSucceeded).source(addr));
+    Ok(user)
+}
+
+/// Runs `f` against the user store on the blocking pool. Store calls do file
+/// or database I/O, and checking a password is slow on purpose.
+async fn with_logins<T: Send + 'static>(f: impl FnOnce(&LoginManager) -> T + Send + 'static) -> anyhow::Result<T> {
+    let logins = LOGINS.get().ok_or_else(|| anyhow::anyhow!("User store not initialized"))?;
+    Ok(tokio::task::spawn_blocking(move || f(logins)).await?)
+}
+
+/// Passes on what the user store said during an attempt, handing the attempt
+/// back. If the store itself failed, that says nothing about the password, so
+/// the attempt is abandoned rather than counted.
+async fn or_abandon<T>(
+    throttle: &Mutex<LoginThrottle>,
+    attempt: Attempt,
+    result: anyhow::Result<T>,
+) -> anyhow::Result<(T, Attempt)> {
+    match result {
+        Ok(result) => Ok((result, attempt)),
+        Err(e) => {
+            throttle.lock().await.abandon(attempt);
+            Err(e)
+        }
+    }
+}
+
+async fn record_failed_login(
+    throttle: &Mutex<LoginThrottle>,
+    attempt: Attempt,
+    username: &str,
+    addr: std::net::SocketAddr,
+    now: u64,
+    reason: LoginFailReason,
+) {
+    record_failure(throttle, attempt, username, addr, now, AuditAction::LoginFailed { reason: reason.to_string() }).await
+}
+
+/// Audits a failed attempt and counts it against the user and address, so
+/// failed registrations and password changes are throttled like logins.
+async fn record_failure(
+    throttle: &Mutex<LoginThrottle>,
+    attempt: Attempt,
+    username: &str,
+    addr: std::net::SocketAddr,
+    now: u64,
+    action: AuditAction,
+) {
+    audit(AuditEvent::new(username, action).source(addr));
+    if throttle.lock().await.failed(attempt, now) {
+        tracing::warn!("Locking out user {} / address {} after repeated failures", username, addr.ip());
+        audit(AuditEvent::new(username, AuditAction::LockedOut).source(addr));
+    }
+}
+
+/// Writes the login throttle out every [`THROTTLE_SAVE_EVERY`] if it has
+/// changed, on the blocking pool, so recording an attempt never waits on the
+/// disk and a burst of failures is one write, not one each.
+async fn save_throttle() {
+    let Some(throttle) = THROTTLE.get() else {
+        return;
+    };
+    let mut interval = tokio::time::interval(THROTTLE_SAVE_EVERY);
+    loop {
+        interval.tick().await;
+        let snapshot = throttle.lock().await.unsaved(login_library2::unix_now());
+        let written = match snapshot {
+            Ok(Some(snapshot)) => tokio::task::spawn_blocking(move || snapshot.write()).await,
+            Ok(None) => continue,
+            Err(e) => Ok(Err(e)),
+        };
+        match written {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => tracing::error!("Failed to save login attempts: {}", e),
+            Err(e) => tracing::error!("Failed to save login attempts: {}", e),
+        }
+    }
+}
+
+/// Appends to the audit log. A failed write is logged but doesn't stop the server.
+fn audit(event: AuditEvent) {
+    let Some(log) = AUDIT.get() else {
+        return;
+    };
+    if let Err(e) = log.record(&event) {
+        tracing::error!("Failed to write to audit log {}: {}", log.path().display(), e);
+    }
 }
 
 async fn handle_connection(mut socket: tokio::net::TcpStream, addr: std::net::SocketAddr) -> anyhow::Result<()> {
     // Magic number and login always happen first
     check_magic_number(&mut socket).await?;
-    let Ok(user) = handle_login(&mut socket).await else {
-        tracing::warn!("Closing connection from {} due to failed login", addr);
-        return Ok(());
+    let (user, session_token) = match handle_login(&mut socket, addr).await {
+        Ok(login) => login,
+        Err(e) => {
+            tracing::warn!("Closing connection from {} due to failed login: {}", addr, e);
+            return Ok(());
+        }
     };
-    tracing::info!("User {} connected from {}", user.username, addr);
-
-    // Find a starting room
-    let starting_room = world_manager::find_starting_room().await?;
-    tracing::info!("User {} starting in room {}", user.username, starting_room);
+    // The User (and its roles) travels with the connection, so privileged commands can be gated on it
+    tracing::info!("User {} connected from {} with roles {:?}", user.username, addr, user.roles());
 
-    // Spawn the player in the world
+    // If the player is still in the world, from a dropped connection or one that's still
+    // open, this connection takes them over. Otherwise spawn them where they were last time.
+    let connection_id = NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed);
     let (player_world_tx, player_world_rx) = tokio::sync::mpsc::channel(32);
-    world_manager::spawn_player(&user.username, &starting_room, player_world_tx.clone()).await?;
+    if world_manager::spawn_player(&user.username, player_world_tx.clone(), connection_id).await? {
+        tracing::info!("User {} took over their existing player", user.username);
+    }
 
-    // Main loop and clean up
-    if let Err(e) = player_loop(socket, &user, player_world_tx, player_world_rx).await {
-        tracing::error!("Error in player loop for {}: {:?}", user.username, e);
+    // Main loop and clean up. A clean quit removes the player; a dropped
+    // connection leaves them in the world for a while so the client can resume.
+    match player_loop(socket, addr, &user, &session_token, player_world_tx, player_world_rx).await {
+        Ok(()) => {
+            sessions::revoke(&session_token);
+            world_manager::despawn_player(&user.username, connection_id).await?;
+            tracing::info!("User {} disconnected", user.username);
+        }
+        Err(e) => {
+            tracing::error!("Error in player loop for {}: {:?}", user.username, e);
+            sessions::expire_after(&session_token, sessions::RESUME_GRACE);
+            world_manager::detach_player(&user.username, connection_id).await?;
+            tracing::info!("User {} dropped; holding their place for {:?}", user.username, sessions::RESUME_GRACE);
+        }
     }
-    world_manager::despawn_player(&user.username).await?;
-    tracing::info!("User {} disconnected", user.username);
 
     Ok(())
 }
 
 async fn player_loop(
     mut socket: tokio::net::TcpStream,
+    addr: std::net::SocketAddr,
     user: &User,
+    session_token: &str,
     player_world_tx: Sender<MudMessage>,
     mut player_world_rx: Receiver<MudMessage>,
 ) -> anyhow::Result<()> {
@@ -96,14 +413,19 @@ async fn player_loop(
 
     loop {
         select! {
-            // If there is an outbound message for the player, send it.
+            // If there is an outbound message for the player, send it. The world
+            // disconnects us if another connection takes over the player.
             Some(message) = player_world_rx.recv() => {
                 send_message(&mut socket_write, &message).await?;
+                if let MudMessage::Disconnect = message {
+                    tracing::info!("Player {} was taken over by another connection", user.username);
+                    break;
+                }
             }
 
             // Process any inbound messages from the player.
             message = read_message(&mut socket_read) => {
-                if let PlayerMessageResult::Disconnect = player_message(message?, user).await? {
+                if let PlayerMessageResult::Disconnect = player_message(message?, addr, user, session_token, &player_world_tx).await? {
                     tracing::info!("Player {} requested disconnect", user.username);
                     break;
                 }
@@ -124,7 +446,13 @@ enum PlayerMessageResult {
     Disconnect,
 }
 
-async fn player_message(message: MudMessage, user: &User) -> anyhow::Result<PlayerMessageResult> {
+async fn player_message(
+    message: MudMessage,
+    addr: std::net::SocketAddr,
+    user: &User,
+    session_token: &str,
+    player_tx: &Sender<MudMessage>,
+) -> anyhow::Result<PlayerMessageResult> {
     match message {
         // Network messages
         MudMessage::Ping => {
@@ -136,10 +464,102 @@ async fn player_message(message: MudMessage, user: &User) -> anyhow::Result<Play
         MudMessage::TryExit { direction } => {
             world_manager::move_player(&user.username, &direction).await?;
         }
+        MudMessage::OperateDoor { action, target } => {
+            world_manager::operate_door(&user.username, action, &target).await?;
+        }
+        MudMessage::GetItem { item } => {
+            world_manager::get_item(&user.username, &item).await?;
+        }
+        MudMessage::DropItem { item } => {
+            world_manager::drop_item(&user.username, &item).await?;
+        }
+        MudMessage::GiveItem { item, username } => {
+            world_manager::give_item(&user.username, &item, &username).await?;
+        }
+        MudMessage::Examine { item } => {
+            world_manager::examine(&user.username, &item).await?;
+        }
+        MudMessage::Inventory => {
+            world_manager::inventory(&user.username).await?;
+        }
         MudMessage::PlayerSpeak { message, .. } => {
             world_manager::player_speak(&user.username, &message).await?;
         }
+        MudMessage::ChangePassword { old_password, new_password } => {
+            player_tx.send(change_password(addr, user, session_token, &old_password, &new_password).await?).await?;
+        }
+        MudMessage::ReloadWorld => {
+            player_tx.send(reload_world(user).await).await?;
+        }
         _ => {}
     }
     Ok(PlayerMessageResult::Continue)
 }
+
+/// Reloads the world for a builder or admin, returning the reply to send.
+async fn reload_world(user: &User) -> MudMessage {
+    if !user.has_role(Role::Builder) {
+        tracing::warn!("User {} tried to reload the world without permission", user.username);
+        return MudMessage::Notice { message: "Only builders and admins can reload the world.".to_string() };
+    }
+    let message = match world_manager::reload().await {
+        Ok(count) => {
+            tracing::info!("User {} reloaded the world", user.username);
+            format!("World reloaded: {count} rooms.")
+        }
+        Err(e) => {
+            tracing::warn!("User {} failed to reload the world: {}", user.username, e);
+            format!("World not reloaded: {e}")
+        }
+    };
+    MudMessage::Notice { message }
+}
+
+/// Handles a player changing their own password, returning the reply to send.
+/// Other sessions they could resume stop working; this one carries on. A wrong
+/// current password counts as a failed login, so it can't be guessed here instead.
+/// If the user store fails, the player is told and their session carries on.
+async fn change_password(
+    addr: std::net::SocketAddr,
+    user: &User,
+    session_token: &str,
+    old_password: &str,
+    new_password: &str,
+) -> anyhow::Result<MudMessage> {
+    let throttle = THROTTLE.get().ok_or_else(|| anyhow::anyhow!("Login throttle not initialized"))?;
+    let now = login_library2::unix_now();
+    let attempt = throttle.lock().await.begin(&user.username, addr.ip(), now);
+    let attempt = match attempt {
+        Ok(attempt) => attempt,
+        Err(block) => {
+            tracing::warn!("User {} tried to change their password while throttled", user.username);
+            let reason = format!("too many failed attempts, try again in {}s", block.retry_after_secs());
+            return Ok(MudMessage::PasswordChangeFail { reason });
+        }
+    };
+    let (username, old_password, new_password) = (user.username.clone(), old_password.to_string(), new_password.to_string());
+    let (result, attempt) = or_abandon(throttle, attempt, with_logins(move |logins| logins.change_password(&username, &old_password, &new_password)).await).await?;
+    Ok(match result {
+        Ok(true) => {
+            throttle.lock().await.succeeded(attempt);
+            let revoked = sessions::revoke_user(&user.username, session_token);
+            tracing::info!("User {} changed their password; revoked {} other session(s)", user.username, revoked);
+            audit(AuditEvent::new(&user.username, AuditAction::PasswordChanged).source(addr));
+            MudMessage::PasswordChanged
+        }
+        Ok(false) => {
+            tracing::warn!("User {} failed to change their password: wrong current password", user.username);
+            record_failure(throttle, attempt, &user.username, addr, now, AuditAction::PasswordChangeFailed).await;
+            MudMessage::PasswordChangeFail { reason: "current password is incorrect".to_string() }
+        }
+        Err(e @ LoginError::PasswordPolicyViolation(_)) => {
+            throttle.lock().await.abandon(attempt);
+            MudMessage::PasswordChangeFail { reason: e.to_string() }
+        }
+        Err(e) => {
+            throttle.lock().await.abandon(attempt);
+            tracing::error!("User {} couldn't change their password: {}", user.username, e);
+            MudMessage::Notice { message: "Your password couldn't be changed just now; please try again later.".to_string() }
+        }
+    })
+}
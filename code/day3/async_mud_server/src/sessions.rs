@@ -0,0 +1,101 @@
+use std::{collections::HashMap, sync::{LazyLock, Mutex}, time::{Duration, Instant}};
+use rand::Rng;
+
+/// How long a dropped player stays in the world, and their session token
+/// stays valid, waiting for the client to reconnect.
+pub const RESUME_GRACE: Duration = Duration::from_secs(5 * 60);
+
+struct Session {
+    /// Only the name: the user is looked up again on resume, so role changes
+    /// made in the meantime apply
+    username: String,
+    /// None while the connection that owns the token is still open
+    expires_at: Option<Instant>,
+}
+
+static SESSIONS: LazyLock<Mutex<HashMap<String, Session>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Issues a new opaque session token for a logged-in user.
+pub fn issue(username: &str) -> String {
+    let bytes: [u8; 32] = rand::rng().random();
+    let token: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+
+    let mut sessions = SESSIONS.lock().unwrap();
+    let now = Instant::now();
+    sessions.retain(|_, s| s.expires_at.is_none_or(|at| at > now));
+    sessions.insert(token.clone(), Session { username: username.to_string(), expires_at: None });
+    token
+}
+
+/// Exchanges a token for the username it was issued to. Tokens are single
+/// use; the caller should issue a fresh one.
+pub fn redeem(token: &str) -> Option<String> {
+    let mut sessions = SESSIONS.lock().unwrap();
+    let session = sessions.remove(token)?;
+    match session.expires_at {
+        Some(at) if at <= Instant::now() => None,
+        _ => Some(session.username),
+    }
+}
+
+/// Starts the expiry clock on a token once its connection drops.
+pub fn expire_after(token: &str, grace: Duration) {
+    if let Some(session) = SESSIONS.lock().unwrap().get_mut(token) {
+        session.expires_at = Some(Instant::now() + grace);
+    }
+}
+
+/// Invalidates a token immediately, e.g. when the player quits.
+pub fn revoke(token: &str) {
+    SESSIONS.lock().unwrap().remove(token);
+}
+
+/// Invalidates every token issued to `username` except `keep`, e.g. when
+/// they change their password. Returns how many were revoked.
+pub fn revoke_user(username: &str, keep: &str) -> usize {
+    let mut sessions = SESSIONS.lock().unwrap();
+    let before = sessions.len();
+    sessions.retain(|token, s| s.username != username || token == keep);
+    before - sessions.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokens_are_single_use() {
+        let token = issue("pat");
+        assert_ne!(token, issue("pat"));
+        assert_eq!(redeem(&token).as_deref(), Some("pat"));
+        assert_eq!(redeem(&token), None);
+        assert_eq!(redeem("not a token"), None);
+    }
+
+    #[test]
+    fn test_tokens_expire_after_grace() {
+        let open = issue("pat");
+        let dropped = issue("pat");
+        let expired = issue("pat");
+        expire_after(&dropped, RESUME_GRACE);
+        expire_after(&expired, Duration::ZERO);
+        assert_eq!(redeem(&open).as_deref(), Some("pat"));
+        assert_eq!(redeem(&dropped).as_deref(), Some("pat"));
+        assert_eq!(redeem(&expired), None);
+    }
+
+    #[test]
+    fn test_revoke() {
+        let token = issue("sam");
+        revoke(&token);
+        assert_eq!(redeem(&token), None);
+
+        let current = issue("sam");
+        let other = issue("sam");
+        let someone_else = issue("alex");
+        assert_eq!(revoke_user("sam", &current), 1);
+        assert_eq!(redeem(&other), None);
+        assert_eq!(redeem(&current).as_deref(), Some("sam"));
+        assert_eq!(redeem(&someone_else).as_deref(), Some("alex"));
+    }
+}
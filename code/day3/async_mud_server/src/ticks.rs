@@ -0,0 +1,239 @@
+use std::{cmp::Reverse, collections::{BinaryHeap, HashMap}, time::Duration};
+use tokio::time::{Interval, MissedTickBehavior};
+
+/// The world's clock, ticking every `period`. Ticks missed while the world
+/// was busy all arrive at once, late, so the [`Scheduler`] catches up rather
+/// than losing time: everything due in the gap still runs, once, in order.
+pub fn clock(period: Duration) -> Interval {
+    let mut clock = tokio::time::interval(period);
+    clock.set_missed_tick_behavior(MissedTickBehavior::Burst);
+    clock
+}
+
+/// Identifies a scheduled event, for cancelling it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EventId(u64);
+
+struct Scheduled<E> {
+    event: E,
+    /// Ticks between repeats, for repeating events
+    every: Option<u64>,
+}
+
+/// Events due after a delay, or repeating, counted in world ticks. Delays
+/// are rounded up to whole ticks, and are never less than one.
+pub struct Scheduler<E> {
+    period: Duration,
+    tick: u64,
+    next_id: u64,
+    /// (due tick, id); ids break ties so events scheduled together run in order
+    queue: BinaryHeap<Reverse<(u64, u64)>>,
+    events: HashMap<u64, Scheduled<E>>,
+}
+
+impl<E: Clone> Scheduler<E> {
+    /// A scheduler for a world that ticks every `period`.
+    pub fn new(period: Duration) -> Self {
+        Self { period, tick: 0, next_id: 0, queue: BinaryHeap::new(), events: HashMap::new() }
+    }
+
+    fn ticks(&self, delay: Duration) -> u64 {
+        (delay.as_nanos().div_ceil(self.period.as_nanos()) as u64).max(1)
+    }
+
+    fn schedule(&mut self, delay: u64, scheduled: Scheduled<E>) -> EventId {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.queue.push(Reverse((self.tick + delay, id)));
+        self.events.insert(id, scheduled);
+        EventId(id)
+    }
+
+    /// Runs `event` once, `delay` from now.
+    pub fn after(&mut self, delay: Duration, event: E) -> EventId {
+        self.schedule(self.ticks(delay), Scheduled { event, every: None })
+    }
+
+    /// Runs `event` every `period` until cancelled, starting one period from now.
+    pub fn every(&mut self, period: Duration, event: E) -> EventId {
+        let every = self.ticks(period);
+        self.schedule(every, Scheduled { event, every: Some(every) })
+    }
+
+    /// Returns false if the event already ran or was cancelled.
+    pub fn cancel(&mut self, id: EventId) -> bool {
+        // Left in the queue, and skipped when it comes due
+        self.events.remove(&id.0).is_some()
+    }
+
+    /// Moves on one tick, returning the events now due in the order they
+    /// were scheduled.
+    pub fn advance(&mut self) -> Vec<E> {
+        self.tick += 1;
+        let mut due = Vec::new();
+        while let Some(Reverse((at, id))) = self.queue.peek().copied()
+            && at <= self.tick
+        {
+            self.queue.pop();
+            let Some(scheduled) = self.events.get(&id) else {
+                continue;
+            };
+            due.push(scheduled.event.clone());
+            match scheduled.every {
+                Some(every) => self.queue.push(Reverse((self.tick + every, id))),
+                None => {
+                    self.events.remove(&id);
+                }
+            }
+        }
+        due
+    }
+}
+
+/// How the world loop has kept up over one reporting window.
+#[derive(Debug, Default)]
+pub struct TickMetrics {
+    ticks: u64,
+    /// Ticks that ran a whole period late, or whose work took longer than a period
+    behind: u64,
+    busy: Duration,
+    max_busy: Duration,
+    max_lag: Duration,
+}
+
+impl TickMetrics {
+    /// Records one tick: `busy` is the time spent handling commands and
+    /// events since the last tick, and `lag` how late this tick ran.
+    pub fn record(&mut self, busy: Duration, lag: Duration, period: Duration) {
+        self.ticks += 1;
+        self.busy += busy;
+        self.max_busy = self.max_busy.max(busy);
+        self.max_lag = self.max_lag.max(lag);
+        if busy > period || lag >= period {
+            self.behind += 1;
+        }
+    }
+
+    /// Logs a summary and starts a new window. Warns if the world fell behind.
+    pub fn report(&mut self) {
+        if self.ticks == 0 {
+            return;
+        }
+        let summary = format!(
+            "{} ticks, busy {:?} on average and {:?} at most, up to {:?} late",
+            self.ticks,
+            self.busy / self.ticks as u32,
+            self.max_busy,
+            self.max_lag,
+        );
+        if self.behind > 0 {
+            tracing::warn!("World fell behind on {} of {}", self.behind, summary);
+        } else {
+            tracing::debug!("World kept up: {}", summary);
+        }
+        *self = Self::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PERIOD: Duration = Duration::from_millis(100);
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn test_events_run_in_order() {
+        let mut scheduler = Scheduler::new(PERIOD);
+        scheduler.after(ms(300), "first at 3");
+        scheduler.after(ms(50), "at 1");
+        scheduler.after(ms(250), "second at 3");
+        scheduler.after(ms(0), "also at 1");
+
+        assert_eq!(scheduler.advance(), vec!["at 1", "also at 1"]);
+        assert!(scheduler.advance().is_empty());
+        assert_eq!(scheduler.advance(), vec!["first at 3", "second at 3"]);
+        assert!(scheduler.advance().is_empty());
+    }
+
+    #[test]
+    fn test_repeating_events_reschedule() {
+        let mut scheduler = Scheduler::new(PERIOD);
+        scheduler.every(ms(200), "every 2");
+        scheduler.after(ms(300), "once");
+
+        let fired: Vec<Vec<&str>> = (0..6).map(|_| scheduler.advance()).collect();
+        assert_eq!(fired, vec![vec![], vec!["every 2"], vec!["once"], vec!["every 2"], vec![], vec!["every 2"]]);
+    }
+
+    #[test]
+    fn test_cancel() {
+        let mut scheduler = Scheduler::new(PERIOD);
+        let once = scheduler.after(ms(200), "once");
+        let repeating = scheduler.every(ms(100), "repeating");
+        let ran = scheduler.after(ms(100), "ran");
+
+        assert!(scheduler.cancel(once));
+        assert!(!scheduler.cancel(once));
+        assert_eq!(scheduler.advance(), vec!["repeating", "ran"]);
+        assert!(!scheduler.cancel(ran));
+        assert!(scheduler.cancel(repeating));
+        for _ in 0..3 {
+            assert!(scheduler.advance().is_empty());
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_catch_up_after_late_tick() {
+        let mut clock = clock(PERIOD);
+        let mut scheduler = Scheduler::new(PERIOD);
+        let mut metrics = TickMetrics::default();
+        scheduler.every(ms(100), "every tick");
+        scheduler.after(ms(200), "at 2");
+        clock.tick().await;
+
+        // The world is stuck for three and a half periods
+        tokio::time::advance(ms(350)).await;
+        let mut fired = Vec::new();
+        for _ in 0..3 {
+            let scheduled = clock.tick().await;
+            metrics.record(Duration::ZERO, scheduled.elapsed(), PERIOD);
+            fired.push(scheduler.advance());
+        }
+        assert_eq!(fired, vec![vec!["every tick"], vec!["every tick", "at 2"], vec!["every tick"]]);
+        // The first two ran at least a period late
+        assert_eq!(metrics.ticks, 3);
+        assert_eq!(metrics.behind, 2);
+        assert_eq!(metrics.max_lag, ms(250));
+
+        // Caught up, the clock keeps its original schedule
+        let scheduled = clock.tick().await;
+        assert_eq!(scheduled.elapsed(), Duration::ZERO);
+        assert_eq!(scheduler.advance(), vec!["every tick"]);
+    }
+
+    #[test]
+    fn test_metrics_count_overruns() {
+        let mut metrics = TickMetrics::default();
+        metrics.record(ms(40), ms(10), PERIOD);
+        metrics.record(ms(100), ms(99), PERIOD);
+        assert_eq!(metrics.behind, 0);
+
+        // Too much work, or a tick a whole period late
+        metrics.record(ms(150), Duration::ZERO, PERIOD);
+        metrics.record(ms(10), ms(100), PERIOD);
+        assert_eq!(metrics.ticks, 4);
+        assert_eq!(metrics.behind, 2);
+        assert_eq!(metrics.busy, ms(300));
+        assert_eq!(metrics.max_busy, ms(150));
+        assert_eq!(metrics.max_lag, ms(100));
+
+        metrics.report();
+        assert_eq!(metrics.ticks, 0);
+        assert_eq!(metrics.behind, 0);
+        assert_eq!(metrics.max_busy, Duration::ZERO);
+    }
+}
@@ -1,100 +1,788 @@
-use std::collections::HashMap;
-use async_mud_proto::MudMessage;
-use tokio::sync::{mpsc::{Receiver, Sender}, OnceCell};
+use std::{collections::{HashMap, HashSet}, path::{Path, PathBuf}, sync::atomic::{AtomicBool, Ordering}, time::{Duration, Instant}};
+use async_mud_proto::{DoorAction, MudMessage};
+use login_library2::unix_now;
+use rooms_library2::{door_id, rooms_within, Door, DoorState, Exit, Item, Npc, PlayerState, PlayerStore, PlayerStoreConfig, Room, RoomError, RoomLibrary};
+use tokio::sync::{mpsc::{error::TrySendError, Receiver, Sender, UnboundedReceiver, UnboundedSender}, OnceCell};
 use rand::prelude::*;
+use crate::sessions::RESUME_GRACE;
+use crate::ticks::{clock, EventId, Scheduler, TickMetrics};
 
 static WORLD_COMMAND_TX: OnceCell<Sender<WorldCommand>> = OnceCell::const_new();
+/// Where the world was loaded from, for reloading it
+static ROOMS_PATH: OnceCell<PathBuf> = OnceCell::const_new();
+/// Requests for the player store task. Unbounded, so the world never waits on the disk.
+static PLAYER_STORE: OnceCell<UnboundedSender<StoreRequest>> = OnceCell::const_new();
 
-pub fn run() -> anyhow::Result<()> {
-    // Load the rooms
-    let rooms = rooms_library2::RoomLibrary::load()?;
-    
-    // Find starting points
-    let starting_rooms: Vec<String> = rooms
+/// How long to wait after the rooms files change before reloading, so an
+/// editor's burst of writes is picked up as one change.
+const RELOAD_SETTLE: Duration = Duration::from_millis(500);
+
+/// How often NPCs who wander think about moving on.
+const NPC_WANDER_EVERY: Duration = Duration::from_secs(1);
+
+/// The chance each time that an NPC who wanders moves to another room.
+const NPC_WANDER_CHANCE: f64 = 0.1;
+
+/// How often items from the world files that have left play are put back.
+const ITEM_RESPAWN_EVERY: Duration = Duration::from_secs(300);
+
+/// How long each part of the day lasts, so a whole day takes an hour.
+const TIME_OF_DAY_LENGTH: Duration = Duration::from_secs(15 * 60);
+
+/// How often to log how well the world is keeping up with its tick.
+const TICK_REPORT_EVERY: Duration = Duration::from_secs(60);
+
+/// Loads and fully validates a world, logging any warnings.
+fn load_world(path: &Path) -> anyhow::Result<HashMap<String, Room>> {
+    let world = RoomLibrary::check(path)?;
+    for warning in world.report.warnings() {
+        tracing::warn!("{}", warning);
+    }
+    if world.report.has_errors() {
+        return Err(RoomError::Invalid(world.report).into());
+    }
+    Ok(world.rooms)
+}
+
+fn find_starting_rooms(rooms: &HashMap<String, Room>) -> Vec<String> {
+    let mut starting_rooms: Vec<String> = rooms
         .iter()
         .filter(|(_name, room)| room.start)
         .map(|(name, _)| name.clone())
-        .collect::<Vec<_>>();
+        .collect();
+    starting_rooms.sort();
+    starting_rooms
+}
+
+/// The state of every door, keyed by its [`door_id`]. Doors already in
+/// `previous` keep their state, so reloading the world doesn't reset them.
+fn door_states(rooms: &HashMap<String, Room>, previous: &HashMap<String, DoorState>) -> HashMap<String, DoorState> {
+    rooms
+        .iter()
+        .flat_map(|(name, room)| room.exits.iter().map(move |exit| (name, exit)))
+        .filter_map(|(name, exit)| {
+            let id = door_id(rooms, name, exit)?;
+            let state = previous.get(&id).copied().or(exit.door.as_ref().map(|door| door.state))?;
+            Some((id, state))
+        })
+        .collect()
+}
+
+/// The state of the door on `exit` out of `room`, as it is now.
+fn door_state(rooms: &HashMap<String, Room>, doors: &HashMap<String, DoorState>, room: &str, exit: &Exit) -> Option<DoorState> {
+    let door = exit.door.as_ref()?;
+    Some(door_id(rooms, room, exit).and_then(|id| doors.get(&id).copied()).unwrap_or(door.state))
+}
+
+/// The rooms with a side of door `id`.
+fn door_sides<'a>(rooms: &'a HashMap<String, Room>, id: &str) -> HashSet<&'a String> {
+    rooms
+        .iter()
+        .filter(|(name, room)| room.exits.iter().any(|exit| door_id(rooms, name, exit).is_some_and(|other| other == id)))
+        .map(|(name, _)| name)
+        .collect()
+}
+
+/// What's lying in each room. On a reload or a respawn, rooms keep whatever is
+/// lying in them, and an item from the world files is only put down if there
+/// isn't already one called that lying anywhere or `carried` by a player,
+/// whether they're in the world or not, so only items that have really left
+/// play come back. New items go at the end of each room's list.
+fn items_by_room<'a>(
+    rooms: &HashMap<String, Room>,
+    mut previous: HashMap<String, Vec<Item>>,
+    carried: impl Iterator<Item = &'a Item>,
+) -> HashMap<String, Vec<Item>> {
+    let mut items: HashMap<String, Vec<Item>> = rooms.keys().map(|name| (name.clone(), previous.remove(name).unwrap_or_default())).collect();
+
+    // Counted by name, so two authored coins still need two coins in play
+    let mut in_play: HashMap<String, usize> = HashMap::new();
+    for item in carried {
+        *in_play.entry(item.name.to_lowercase()).or_default() += 1;
+    }
+    for item in items.values().flatten() {
+        *in_play.entry(item.name.to_lowercase()).or_default() += 1;
+    }
+    let mut names: Vec<&String> = rooms.keys().collect();
+    names.sort();
+    for name in names {
+        for item in &rooms[name].items {
+            match in_play.get_mut(&item.name.to_lowercase()) {
+                Some(count) if *count > 0 => *count -= 1,
+                _ => items.get_mut(name).unwrap().push(item.clone()),
+            }
+        }
+    }
+    items
+}
+
+/// Removes the item called `name` from `items`.
+fn take_item(items: &mut Vec<Item>, name: &str) -> Option<Item> {
+    let index = items.iter().position(|item| item.is_called(name))?;
+    Some(items.remove(index))
+}
+
+/// Where the world is in its day, which moves on every [`TIME_OF_DAY_LENGTH`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TimeOfDay {
+    Dawn,
+    Day,
+    Dusk,
+    Night,
+}
+
+impl TimeOfDay {
+    fn next(self) -> Self {
+        match self {
+            TimeOfDay::Dawn => TimeOfDay::Day,
+            TimeOfDay::Day => TimeOfDay::Dusk,
+            TimeOfDay::Dusk => TimeOfDay::Night,
+            TimeOfDay::Night => TimeOfDay::Dawn,
+        }
+    }
+
+    /// What everyone in the world is told as it begins.
+    fn announcement(self) -> &'static str {
+        match self {
+            TimeOfDay::Dawn => "The sky lightens as the sun rises.",
+            TimeOfDay::Day => "The sun is high overhead.",
+            TimeOfDay::Dusk => "The light fades as the sun sets.",
+            TimeOfDay::Night => "Night falls.",
+        }
+    }
+}
+
+/// An NPC out in the world.
+#[derive(Clone, Debug)]
+struct NpcState {
+    npc: Npc,
+    home: String,
+    room: String,
+    /// The rooms within their wander radius of home
+    range: HashSet<String>,
+}
+
+/// Puts every NPC in the world. On a reload, NPCs keep their place if it's
+/// still within their range, and otherwise go home.
+fn spawn_npcs(rooms: &HashMap<String, Room>, previous: Vec<NpcState>) -> Vec<NpcState> {
+    let mut previous: HashMap<String, NpcState> = previous.into_iter().map(|n| (n.npc.name.to_lowercase(), n)).collect();
+    rooms
+        .iter()
+        .flat_map(|(home, room)| room.npcs.iter().map(move |npc| (home, npc)))
+        .map(|(home, npc)| {
+            let range = rooms_within(rooms, home, npc.wander);
+            let room = previous
+                .remove(&npc.name.to_lowercase())
+                .map(|old| old.room)
+                .filter(|room| range.contains(room))
+                .unwrap_or_else(|| home.clone());
+            NpcState { npc: npc.clone(), home: home.clone(), room, range }
+        })
+        .collect()
+}
+
+/// Where the world sends a player's messages, remembering whether any had to
+/// be dropped.
+#[derive(Debug)]
+struct PlayerTx {
+    tx: Sender<MudMessage>,
+    behind: AtomicBool,
+}
+
+impl PlayerTx {
+    fn new(tx: Sender<MudMessage>) -> Self {
+        Self { tx, behind: AtomicBool::new(false) }
+    }
+
+    /// True once a player who missed messages has room in their queue for a
+    /// refresh, which it takes: they're not behind any more.
+    fn catch_up(&self) -> bool {
+        self.tx.capacity() >= REFRESH_MESSAGES && self.behind.swap(false, Ordering::Relaxed)
+    }
+}
+
+/// How many messages it takes to show a player the world afresh: their room
+/// and their inventory.
+const REFRESH_MESSAGES: usize = 2;
+
+/// Sends `message` to a player without waiting. The world loop never waits
+/// on a client: one that has stopped reading would stall everyone else, and
+/// the clock with them. If their queue is full the message is dropped, and
+/// they're sent their room and inventory again once they catch up, so what
+/// they see doesn't drift from the world.
+fn send_to(player_tx: &PlayerTx, message: MudMessage) {
+    if let Err(TrySendError::Full(_)) = player_tx.tx.try_send(message) {
+        tracing::warn!("Dropped a message for a client that isn't keeping up");
+        player_tx.behind.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Shows everyone who missed messages their room and inventory as they are
+/// now, once they have room for them.
+fn refresh_behind(
+    rooms: &HashMap<String, Room>,
+    doors: &HashMap<String, DoorState>,
+    room_items: &HashMap<String, Vec<Item>>,
+    npcs: &[NpcState],
+    players: &[Player],
+) {
+    for player in players.iter().filter(|p| p.player_tx.catch_up()) {
+        tracing::info!("Player {} caught up; sending their room and inventory again", player.username);
+        send_room(rooms, doors, room_items, npcs, players, &player.username);
+        send_to(&player.player_tx, MudMessage::InventoryList { items: player.inventory.clone() });
+    }
+}
+
+/// Has the NPCs in `room` greet a player who just came in.
+fn greet(npcs: &[NpcState], room: &str, player_tx: &PlayerTx) {
+    for npc in npcs.iter().filter(|n| n.room == room) {
+        if let Some(greeting) = &npc.npc.greeting {
+            send_to(player_tx, MudMessage::NpcSpeak { name: npc.npc.name.clone(), message: greeting.clone() });
+        }
+    }
+}
+
+/// Everything players are carrying: those in the world, and what the rest
+/// took with them when they left.
+fn carried<'a>(players: &'a [Player], offline: &'a HashMap<String, Vec<Item>>) -> impl Iterator<Item = &'a Item> {
+    players.iter().flat_map(|p| &p.inventory).chain(offline.values().flatten())
+}
+
+/// Takes players out of the world, saving their progress and noting in
+/// `offline` what they took with them.
+fn remove_players(players: &mut Vec<Player>, offline: &mut HashMap<String, Vec<Item>>, remove: impl Fn(&Player) -> bool) -> usize {
+    let removed: Vec<Player> = players.extract_if(.., |p| remove(p)).collect();
+    save_players(removed.iter().map(Player::state).collect());
+    for player in &removed {
+        offline.insert(player.username.clone(), player.inventory.clone());
+    }
+    removed.len()
+}
+
+/// Has `username` pick up `item` from the room they're in.
+fn pick_up(players: &mut [Player], room_items: &mut HashMap<String, Vec<Item>>, username: &str, item: &str) {
+    let Some(player) = players.iter_mut().find(|p| p.username == username) else {
+        tracing::warn!("Player {} not found for get command", username);
+        return;
+    };
+    let Some(taken) = room_items.get_mut(&player.room).and_then(|items| take_item(items, item)) else {
+        send_to(&player.player_tx, MudMessage::Notice { message: format!("There is no {} here.", item.trim()) });
+        return;
+    };
+    send_to(&player.player_tx, MudMessage::Notice { message: format!("You pick up the {}.", taken.name) });
+    let room = player.room.clone();
+    let name = taken.name.clone();
+    player.inventory.push(taken);
+    tracing::info!("Player {} picked up {} in room {}", username, name, room);
+    for p in players.iter().filter(|p| p.room == room && p.username != username) {
+        send_to(&p.player_tx, MudMessage::PlayerGotItem { username: username.to_string(), item: name.clone() });
+    }
+}
+
+/// Has `username` put down `item` in the room they're in.
+fn put_down(players: &mut [Player], room_items: &mut HashMap<String, Vec<Item>>, username: &str, item: &str) {
+    let Some(player) = players.iter_mut().find(|p| p.username == username) else {
+        tracing::warn!("Player {} not found for drop command", username);
+        return;
+    };
+    let Some(dropped) = take_item(&mut player.inventory, item) else {
+        send_to(&player.player_tx, MudMessage::Notice { message: format!("You aren't carrying a {}.", item.trim()) });
+        return;
+    };
+    send_to(&player.player_tx, MudMessage::Notice { message: format!("You drop the {}.", dropped.name) });
+    let room = player.room.clone();
+    let name = dropped.name.clone();
+    room_items.entry(room.clone()).or_default().push(dropped);
+    tracing::info!("Player {} dropped {} in room {}", username, name, room);
+    for p in players.iter().filter(|p| p.room == room && p.username != username) {
+        send_to(&p.player_tx, MudMessage::PlayerDroppedItem { username: username.to_string(), item: name.clone() });
+    }
+}
+
+/// Has `username` hand `item` to `recipient`, who must be in the same room.
+fn hand_over(players: &mut [Player], username: &str, item: &str, recipient: &str) {
+    let Some(giver) = players.iter().position(|p| p.username == username) else {
+        tracing::warn!("Player {} not found for give command", username);
+        return;
+    };
+    let room = players[giver].room.clone();
+    let Some(receiver) = players.iter().position(|p| p.room == room && p.username.eq_ignore_ascii_case(recipient.trim()) && p.username != username) else {
+        send_to(&players[giver].player_tx, MudMessage::Notice { message: format!("There is nobody called {} here.", recipient.trim()) });
+        return;
+    };
+    let Some(given) = take_item(&mut players[giver].inventory, item) else {
+        send_to(&players[giver].player_tx, MudMessage::Notice { message: format!("You aren't carrying a {}.", item.trim()) });
+        return;
+    };
+    let name = given.name.clone();
+    let recipient = players[receiver].username.clone();
+    players[receiver].inventory.push(given);
+    tracing::info!("Player {} gave {} to {} in room {}", username, name, recipient, room);
+
+    send_to(&players[giver].player_tx, MudMessage::Notice { message: format!("You give the {name} to {recipient}.") });
+    let message = format!("{username} gives you the {name}.");
+    send_to(&players[receiver].player_tx, MudMessage::Notice { message });
+    for p in players.iter().filter(|p| p.room == room && p.username != username && p.username != recipient) {
+        send_to(&p.player_tx, MudMessage::PlayerGaveItem {
+            username: username.to_string(),
+            item: name.clone(),
+            recipient: recipient.clone(),
+        });
+    }
+}
+
+/// Work for the player store task, done one at a time in the order asked.
+/// A load asked for after a save sees what was saved.
+enum StoreRequest {
+    Save(Vec<PlayerState>),
+    Load { username: String, reply: tokio::sync::oneshot::Sender<anyhow::Result<Option<PlayerState>>> },
+}
+
+/// Owns the player store, doing its file I/O on the blocking pool so the
+/// world loop only ever hands over snapshots and carries on.
+async fn run_player_store(mut store: Box<dyn PlayerStore>, mut requests: UnboundedReceiver<StoreRequest>) {
+    while let Some(request) = requests.recv().await {
+        let done = tokio::task::spawn_blocking(move || {
+            match request {
+                StoreRequest::Save(states) => match store.save_all(&states) {
+                    Ok(()) => tracing::debug!("Saved {} player(s)", states.len()),
+                    // Nothing better for the world to do than carry on
+                    Err(e) => tracing::error!("Failed to save {} player(s): {}", states.len(), e),
+                },
+                StoreRequest::Load { username, reply } => {
+                    let _ = reply.send(store.load(&username).map_err(Into::into));
+                }
+            }
+            store
+        }).await;
+        match done {
+            Ok(returned) => store = returned,
+            Err(e) => {
+                tracing::error!("Player store task failed; players will no longer be saved: {}", e);
+                return;
+            }
+        }
+    }
+}
+
+/// Queues a save of these snapshots of players' progress.
+fn save_players(states: Vec<PlayerState>) {
+    if states.is_empty() {
+        return;
+    }
+    if PLAYER_STORE.get().is_none_or(|store| store.send(StoreRequest::Save(states)).is_err()) {
+        tracing::error!("Player store not running; progress not saved");
+    }
+}
+
+/// Loads a player's progress off the world loop, then hands them back to it
+/// as a [`WorldCommand::PlayerLoaded`] to finish spawning.
+fn load_player(username: String, player_tx: Sender<MudMessage>, connection_id: u64, reply: tokio::sync::oneshot::Sender<anyhow::Result<bool>>) {
+    let (loaded_tx, loaded_rx) = tokio::sync::oneshot::channel();
+    let request = StoreRequest::Load { username: username.clone(), reply: loaded_tx };
+    if PLAYER_STORE.get().is_none_or(|store| store.send(request).is_err()) {
+        let _ = reply.send(Err(anyhow::anyhow!("Player store not running")));
+        return;
+    }
+    tokio::spawn(async move {
+        let saved = loaded_rx.await.unwrap_or_else(|_| Err(anyhow::anyhow!("Player store stopped")));
+        if let Some(tx) = WORLD_COMMAND_TX.get() {
+            let _ = tx.send(WorldCommand::PlayerLoaded { username, player_tx, connection_id, saved, reply }).await;
+        }
+    });
+}
+
+/// Quietly hands `username` to a new connection if they're already in the
+/// world; the rest of the room never saw them leave. If their old connection
+/// is still open, it's closed so it can't act for them. Returns false if
+/// they aren't in the world.
+fn take_over(players: &mut [Player], username: &str, player_tx: &Sender<MudMessage>, connection_id: u64) -> bool {
+    let Some(player) = players.iter_mut().find(|p| p.username == username) else {
+        return false;
+    };
+    if player.detached_at.is_none() {
+        tracing::info!("Player {} connected again; closing connection {}", username, player.connection_id);
+        let message = "You have connected from somewhere else.".to_string();
+        send_to(&player.player_tx, MudMessage::Notice { message });
+        // The close mustn't be lost to a full queue, but the world can't wait for it
+        let old_tx = player.player_tx.tx.clone();
+        tokio::spawn(async move {
+            let _ = old_tx.send(MudMessage::Disconnect).await;
+        });
+    }
+    player.player_tx = PlayerTx::new(player_tx.clone());
+    player.connection_id = connection_id;
+    player.detached_at = None;
+    tracing::info!("Player {} reattached in room {}", username, player.room);
+    true
+}
+
+/// Shows a player the room they're in.
+fn send_room(
+    rooms: &HashMap<String, Room>,
+    doors: &HashMap<String, DoorState>,
+    room_items: &HashMap<String, Vec<Item>>,
+    npcs: &[NpcState],
+    players: &[Player],
+    username: &str,
+) {
+    let Some(player) = players.iter().find(|p| p.username == username) else {
+        return;
+    };
+    match enter_room(rooms, doors, room_items, npcs, players, &player.room, username) {
+        Some(message) => {
+            send_to(&player.player_tx, message);
+        }
+        None => tracing::error!("Room {} not found for player {}", player.room, username),
+    }
+}
+
+/// What `action` does to a door in `state`, or why it can't be done.
+fn door_after(door: &Door, state: DoorState, action: DoorAction, has_key: bool) -> Result<DoorState, String> {
+    let name = &door.name;
+    match (action, state) {
+        (DoorAction::Open, DoorState::Open) => Err(format!("The {name} is already open.")),
+        (DoorAction::Open, DoorState::Locked) => Err(format!("The {name} is locked.")),
+        (DoorAction::Open, DoorState::Closed) => Ok(DoorState::Open),
+        (DoorAction::Close, DoorState::Open) => Ok(DoorState::Closed),
+        (DoorAction::Close, _) => Err(format!("The {name} is already closed.")),
+        (DoorAction::Lock | DoorAction::Unlock, _) if door.key.is_none() => Err(format!("The {name} has no lock.")),
+        (DoorAction::Lock, DoorState::Open) => Err(format!("You need to close the {name} first.")),
+        (DoorAction::Lock, DoorState::Locked) => Err(format!("The {name} is already locked.")),
+        (DoorAction::Unlock, DoorState::Open | DoorState::Closed) => Err(format!("The {name} isn't locked.")),
+        (DoorAction::Lock | DoorAction::Unlock, _) if !has_key => Err(format!("You don't have the key to the {name}.")),
+        (DoorAction::Lock, DoorState::Closed) => Ok(DoorState::Locked),
+        (DoorAction::Unlock, DoorState::Locked) => Ok(DoorState::Closed),
+    }
+}
+
+/// Starts the world, ticking every `tick`. Players' progress is kept in
+/// `players`, saved when they leave and every `save_interval` while they play.
+pub fn run(rooms_path: &Path, watch: bool, players: &PlayerStoreConfig, save_interval: Option<Duration>, tick: Duration) -> anyhow::Result<()> {
+    // Load the rooms
+    tracing::info!("Loading rooms from {}", rooms_path.display());
+    let rooms = load_world(rooms_path)?;
+
+    // Find starting points
+    let starting_rooms = find_starting_rooms(&rooms);
     if starting_rooms.is_empty() {
         return Err(anyhow::anyhow!("No starting rooms found in the room library"));
     }
 
     let (tx, rx) = tokio::sync::mpsc::channel(100);
-    WORLD_COMMAND_TX.set(tx)?;
+    WORLD_COMMAND_TX.set(tx.clone())?;
+    ROOMS_PATH.set(rooms_path.to_path_buf())?;
+    let (store_tx, store_rx) = tokio::sync::mpsc::unbounded_channel();
+    PLAYER_STORE.set(store_tx).map_err(|_| anyhow::anyhow!("Player store already initialized"))?;
+
+    // What saved players are carrying is still in play while they're away
+    let mut store = players.open();
+    let offline = store.load_all()?.into_iter().map(|saved| (saved.username, saved.inventory)).collect();
+    tokio::spawn(run_player_store(store, store_rx));
+
+    if watch {
+        watch_rooms(rooms_path)?;
+    }
 
     // Start the main loop
     tokio::spawn(async move {
-        main_loop(rooms, starting_rooms, rx).await;
+        main_loop(rooms, starting_rooms, offline, rx, tick, save_interval).await;
     });
 
     Ok(())
 }
 
+/// Reloads the world whenever the rooms file, or anything in the zone
+/// directory, changes. A world that doesn't validate is logged and ignored.
+fn watch_rooms(path: &Path) -> anyhow::Result<()> {
+    use notify::{EventKind, RecursiveMode, Watcher};
+
+    // Editors often replace a file rather than writing to it, so a single
+    // rooms file is watched through its directory
+    let (watched, mode, file_name) = if path.is_dir() {
+        (path.to_path_buf(), RecursiveMode::Recursive, None)
+    } else {
+        let parent = path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        (parent.to_path_buf(), RecursiveMode::NonRecursive, path.file_name().map(|name| name.to_os_string()))
+    };
+
+    let (changed_tx, mut changed_rx) = tokio::sync::mpsc::channel(1);
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let Ok(event) = event else {
+            return;
+        };
+        if matches!(event.kind, EventKind::Access(_)) {
+            return;
+        }
+        if let Some(name) = &file_name
+            && !event.paths.iter().any(|p| p.file_name() == Some(name))
+        {
+            return;
+        }
+        // If the channel is full, a reload is already on its way
+        let _ = changed_tx.try_send(());
+    })?;
+    watcher.watch(&watched, mode)?;
+    tracing::info!("Watching {} for changes to the rooms", watched.display());
+
+    tokio::spawn(async move {
+        // The watcher stops when dropped, so keep it here
+        let _watcher = watcher;
+        while changed_rx.recv().await.is_some() {
+            tokio::time::sleep(RELOAD_SETTLE).await;
+            while changed_rx.try_recv().is_ok() {}
+            match reload().await {
+                Ok(count) => tracing::info!("Rooms changed on disk; reloaded {} rooms", count),
+                Err(e) => tracing::error!("Rooms changed on disk but were not reloaded: {}", e),
+            }
+        }
+    });
+    Ok(())
+}
+
 enum WorldCommand {
-    FindStartingRoom { reply: tokio::sync::oneshot::Sender<String>},
-    PlayerSpawn { username: String, room: String, player_tx: Sender<MudMessage> },
-    DespawnPlayer { username: String },
+    /// Hands a player who is already in the world to a new connection, or
+    /// else puts them back where they left off, or in a start room if
+    /// they're new or their room is gone. Replies true for a takeover.
+    PlayerSpawn { username: String, player_tx: Sender<MudMessage>, connection_id: u64, reply: tokio::sync::oneshot::Sender<anyhow::Result<bool>> },
+    /// A spawning player's saved progress has been read; see [`load_player`]
+    PlayerLoaded {
+        username: String,
+        player_tx: Sender<MudMessage>,
+        connection_id: u64,
+        saved: anyhow::Result<Option<PlayerState>>,
+        reply: tokio::sync::oneshot::Sender<anyhow::Result<bool>>,
+    },
+    DetachPlayer { username: String, connection_id: u64 },
+    ExpireDetached { username: String },
+    DespawnPlayer { username: String, connection_id: u64 },
     PlayerMove { username: String, direction: String },
+    OperateDoor { username: String, action: DoorAction, target: String },
+    GetItem { username: String, item: String },
+    DropItem { username: String, item: String },
+    GiveItem { username: String, item: String, recipient: String },
+    Examine { username: String, item: String },
+    Inventory { username: String },
     Speak { username: String, message: String },
+    /// Swap in a freshly loaded and validated world
+    ReplaceWorld { rooms: HashMap<String, Room> },
+    /// Time passes. Never sent: the world loop makes these itself, every tick.
+    Tick { scheduled: tokio::time::Instant },
 }
 
-#[derive(Clone, Debug)]
+/// Things that happen in the world on a timer rather than because a player did them.
+#[derive(Debug, Clone)]
+enum WorldEvent {
+    NpcWander,
+    /// Closes a door that shuts by itself, if it's still open
+    CloseDoor { id: String, name: String },
+    /// Put back items from the world files that have left play
+    RespawnItems,
+    /// Move on to the next part of the day
+    TimeOfDay,
+    /// Save everyone's progress, in case the server stops unexpectedly
+    SaveAll,
+    ReportTicks,
+}
+
+#[derive(Debug)]
 struct Player {
     username: String,
     room: String,
-    player_tx: Sender<MudMessage>,
+    player_tx: PlayerTx,
+    /// Which connection currently drives this player, so a stale connection
+    /// closing can't remove a player that has since been resumed elsewhere.
+    connection_id: u64,
+    /// Set when the connection dropped and we're waiting for a resume
+    detached_at: Option<Instant>,
+    inventory: Vec<Item>,
+    /// Seconds since the Unix epoch
+    first_seen: u64,
+    /// Play time from earlier sessions
+    play_time_secs: u64,
+    joined: Instant,
+}
+
+impl Player {
+    fn state(&self) -> PlayerState {
+        PlayerState {
+            username: self.username.clone(),
+            room: self.room.clone(),
+            inventory: self.inventory.clone(),
+            first_seen: self.first_seen,
+            last_seen: unix_now(),
+            play_time_secs: self.play_time_secs + self.joined.elapsed().as_secs(),
+        }
+    }
+}
+
+/// The EnterRoom message for a player in `room`, listing everyone else there
+/// and showing its doors and items as they are now.
+fn enter_room(
+    rooms: &HashMap<String, Room>,
+    doors: &HashMap<String, DoorState>,
+    room_items: &HashMap<String, Vec<Item>>,
+    npcs: &[NpcState],
+    players: &[Player],
+    room: &str,
+    username: &str,
+) -> Option<MudMessage> {
+    let mut room_details = rooms.get(room)?.clone();
+    for exit in room_details.exits.iter_mut() {
+        if let Some(state) = door_state(rooms, doors, room, exit)
+            && let Some(door) = exit.door.as_mut()
+        {
+            door.state = state;
+        }
+    }
+    room_details.items = room_items.get(room).cloned().unwrap_or_default();
+    let other_players = players.iter()
+        .filter(|p| p.room == room && p.username != username)
+        .map(|p| p.username.clone())
+        .collect();
+    let npcs = npcs.iter().filter(|n| n.room == room).map(|n| n.npc.name.clone()).collect();
+    Some(MudMessage::EnterRoom { room: room_details, other_players, npcs })
 }
 
 async fn main_loop(
-    rooms: HashMap<String, rooms_library2::Room>,
-    starting_rooms: Vec<String>,
+    mut rooms: HashMap<String, Room>,
+    mut starting_rooms: Vec<String>,
+    mut offline: HashMap<String, Vec<Item>>,
     mut world_commands: Receiver<WorldCommand>,
+    tick_period: Duration,
+    save_interval: Option<Duration>,
 ) {
     let mut players: Vec<Player> = Vec::new();
+    let mut doors = door_states(&rooms, &HashMap::new());
+    let mut room_items = items_by_room(&rooms, HashMap::new(), carried(&players, &offline));
+    let mut npcs = spawn_npcs(&rooms, Vec::new());
+    let mut time_of_day = TimeOfDay::Day;
+
+    let mut scheduler = Scheduler::new(tick_period);
+    scheduler.every(NPC_WANDER_EVERY, WorldEvent::NpcWander);
+    scheduler.every(ITEM_RESPAWN_EVERY, WorldEvent::RespawnItems);
+    scheduler.every(TIME_OF_DAY_LENGTH, WorldEvent::TimeOfDay);
+    scheduler.every(TICK_REPORT_EVERY, WorldEvent::ReportTicks);
+    if let Some(every) = save_interval {
+        scheduler.every(every, WorldEvent::SaveAll);
+    }
+    // Doors due to swing shut, by door id
+    let mut door_closers: HashMap<String, EventId> = HashMap::new();
+
+    let mut tick = clock(tick_period);
+    let mut metrics = TickMetrics::default();
+    let mut busy = Duration::ZERO;
+    let mut busy_since: Option<Instant> = None;
+    loop {
+        if let Some(started) = busy_since.take() {
+            busy += started.elapsed();
+        }
+        let command = tokio::select! {
+            command = world_commands.recv() => match command {
+                Some(command) => command,
+                None => break,
+            },
+            scheduled = tick.tick() => WorldCommand::Tick { scheduled },
+        };
+        busy_since = Some(Instant::now());
 
-    while let Some(command) = world_commands.recv().await {
         match command {
-            WorldCommand::FindStartingRoom { reply } => {
-                let room = starting_rooms
-                    .choose(&mut rand::rng())
-                    .cloned()
-                    .unwrap_or_else(|| starting_rooms[0].clone());
-                if reply.send(room).is_err() {
-                    tracing::warn!("Failed to send starting room");
-                }
-            }
-            WorldCommand::PlayerSpawn { username, room, player_tx } => {
-                if rooms.contains_key(&room) {
-                    tracing::info!("Player {} spawned in room {}", username, room);
-
-                    // Send the EnterRoom message to the player
-                    let Some(room_details) = rooms.get(&room) else {
-                        tracing::error!("Room {} not found after existence check", room);
+            WorldCommand::PlayerSpawn { username, player_tx, connection_id, reply } => {
+                if take_over(&mut players, &username, &player_tx, connection_id) {
+                    send_room(&rooms, &doors, &room_items, &npcs, &players, &username);
+                    let _ = reply.send(Ok(true));
+                    continue;
+                }
+                load_player(username, player_tx, connection_id, reply);
+            }
+            WorldCommand::PlayerLoaded { username, player_tx, connection_id, saved, reply } => {
+                // Another connection may have spawned them while this one was loading
+                if take_over(&mut players, &username, &player_tx, connection_id) {
+                    send_room(&rooms, &doors, &room_items, &npcs, &players, &username);
+                    let _ = reply.send(Ok(true));
+                    continue;
+                }
+                let saved = match saved {
+                    Ok(saved) => saved,
+                    Err(e) => {
+                        let _ = reply.send(Err(e.context(format!("Failed to load player {username}"))));
                         continue;
-                    };
-                    let other_players: Vec<String> = players.iter()
-                        .filter(|p| p.room == room && p.username != username)
-                        .map(|p| p.username.clone())
-                        .collect();
-                    player_tx.send(
-                        MudMessage::EnterRoom { room: room_details.clone(), other_players }
-                    ).await.unwrap_or_else(|e| {
-                        tracing::warn!("Failed to send EnterRoom message to player {}: {:?}", username, e);
-                    });
-
-                    // Tell any other players in the room that this player has entered
-                    for p in players.iter().filter(|p| p.room == room) {
-                        let _ = p.player_tx.send(MudMessage::PlayerEnteredRoom { username: username.clone() }).await;
                     }
+                };
+                let player_tx = PlayerTx::new(player_tx);
+                let room = match &saved {
+                    Some(saved) if rooms.contains_key(&saved.room) => saved.room.clone(),
+                    _ => {
+                        let Some(start) = starting_rooms.choose(&mut rand::rng()) else {
+                            let _ = reply.send(Err(anyhow::anyhow!("No starting room for player {username}")));
+                            continue;
+                        };
+                        if let Some(saved) = &saved {
+                            let notice = format!("{} is no longer part of the world, so you start in {}.", saved.room, start);
+                            send_to(&player_tx, MudMessage::Notice { message: notice });
+                        }
+                        start.clone()
+                    }
+                };
+                match &saved {
+                    Some(saved) => tracing::info!("Player {} returned to room {} (first seen {})", username, room, saved.first_seen),
+                    None => tracing::info!("New player {} spawned in room {}", username, room),
+                }
 
-                    // Add them to the players list
-                    players.push(Player { username, room, player_tx });
-                } else {
-                    tracing::warn!("Room {} does not exist", room);
+                // Send the EnterRoom message to the player
+                let Some(message) = enter_room(&rooms, &doors, &room_items, &npcs, &players, &room, &username) else {
+                    let _ = reply.send(Err(anyhow::anyhow!("Room {room} not found after existence check")));
+                    continue;
+                };
+                send_to(&player_tx, message);
+                greet(&npcs, &room, &player_tx);
+
+                // Tell any other players in the room that this player has entered
+                for p in players.iter().filter(|p| p.room == room) {
+                    send_to(&p.player_tx, MudMessage::PlayerEnteredRoom { username: username.clone() });
                 }
+
+                // Add them to the players list, bringing what they carry back into the world
+                offline.remove(&username);
+                let saved = saved.unwrap_or_else(|| PlayerState::new(&username, &room, unix_now()));
+                players.push(Player {
+                    username,
+                    room,
+                    player_tx,
+                    connection_id,
+                    detached_at: None,
+                    inventory: saved.inventory,
+                    first_seen: saved.first_seen,
+                    play_time_secs: saved.play_time_secs,
+                    joined: Instant::now(),
+                });
+                let _ = reply.send(Ok(false));
             }
-            WorldCommand::DespawnPlayer { username } => {
-                players.retain(|player| player.username != username);
+            WorldCommand::DetachPlayer { username, connection_id } => {
+                let Some(player) = players.iter_mut().find(|p| p.username == username && p.connection_id == connection_id) else {
+                    continue;
+                };
+                player.detached_at = Some(Instant::now());
+                tracing::info!("Player {} detached, waiting for them to resume", username);
+
+                // Come back later and remove them if they haven't reconnected
+                tokio::spawn(async move {
+                    tokio::time::sleep(RESUME_GRACE).await;
+                    if let Some(tx) = WORLD_COMMAND_TX.get() {
+                        let _ = tx.send(WorldCommand::ExpireDetached { username }).await;
+                    }
+                });
+            }
+            WorldCommand::ExpireDetached { username } => {
+                let expired = remove_players(&mut players, &mut offline, |p| {
+                    p.username == username && p.detached_at.is_some_and(|at| at.elapsed() >= RESUME_GRACE)
+                });
+                if expired > 0 {
+                    tracing::info!("Player {} did not resume in time and was despawned", username);
+                }
+            }
+            WorldCommand::DespawnPlayer { username, connection_id } => {
+                remove_players(&mut players, &mut offline, |p| p.username == username && p.connection_id == connection_id);
                 tracing::info!("Player {} despawned", username);
             }
             WorldCommand::PlayerMove { username, direction } => {
@@ -109,37 +797,139 @@ async fn main_loop(
                     tracing::warn!("Current room {} for player {} not found", player.room, username);
                     continue;
                 };
-                let Some(next_room_name) = current_room.exits.iter().find(|e| e.direction == direction) else {
-                    tracing::warn!("No exit {} in room {} for player {}", direction, current_room.name, username);
+                let Some(exit) = current_room.find_exit(&direction) else {
+                    tracing::info!("No exit {} in room {} for player {}", direction, current_room.name, username);
+                    send_to(&player.player_tx, MudMessage::Notice { message: format!("You can't go {direction} from here.") });
                     continue;
                 };
-                // To avoid borrow issues below, clone the player
-                let player = (*player).clone();
+                if let Some(door) = &exit.door
+                    && let Some(state) = door_state(&rooms, &doors, &player.room, exit)
+                    && state != DoorState::Open
+                {
+                    send_to(&player.player_tx, MudMessage::Notice { message: format!("The {} is {state}.", door.name) });
+                    continue;
+                }
+                // To avoid borrow issues below, clone what we need
+                let from = player.room.clone();
+                let exit = exit.clone();
 
                 // Notify other players in the current room that this player is leaving
-                for p in players.iter().filter(|p| p.room == player.room && p.username != username) {
-                    let _ = p.player_tx.send(MudMessage::PlayerLeftRoom { username: username.clone(), direction: direction.clone() }).await;
+                for p in players.iter().filter(|p| p.room == from && p.username != username) {
+                    send_to(&p.player_tx, MudMessage::PlayerLeftRoom { username: username.clone(), direction: exit.direction.clone() });
                 }
 
                 // Move the player - iterating to avoid borrow issues
-                players.iter_mut().find(|p| p.username == username).map(|p| p.room = next_room_name.room_name.clone());
+                if let Some(p) = players.iter_mut().find(|p| p.username == username) {
+                    p.room = exit.room_name.clone();
+                }
+                let Some(player) = players.iter().find(|p| p.username == username) else {
+                    continue;
+                };
 
                 // Notify the player of the new room
-                if let Some(new_room) = rooms.get(&player.room) {
-                    let other_players: Vec<String> = players.iter()
-                        .filter(|p| p.room == player.room && p.username != username)
-                        .map(|p| p.username.clone())
-                        .collect();
-                    let _ = player.player_tx.send(MudMessage::EnterRoom { room: new_room.clone(), other_players }).await;
-                } else {
-                    tracing::error!("Next room {} not found for player {}", player.room, username);
+                match enter_room(&rooms, &doors, &room_items, &npcs, &players, &exit.room_name, &username) {
+                    Some(message) => {
+                        send_to(&player.player_tx, message);
+                        greet(&npcs, &exit.room_name, &player.player_tx);
+                    }
+                    None => tracing::error!("Next room {} not found for player {}", exit.room_name, username),
                 }
 
                 // Notify other players in the new room that this player has entered
-                for p in players.iter().filter(|p| p.room == player.room && p.username != username) {
-                    let _ = p.player_tx.send(MudMessage::PlayerEnteredRoom { username: username.clone() }).await;
+                for p in players.iter().filter(|p| p.room == exit.room_name && p.username != username) {
+                    send_to(&p.player_tx, MudMessage::PlayerEnteredRoom { username: username.clone() });
                 }
             }
+            WorldCommand::OperateDoor { username, action, target } => {
+                let Some(player) = players.iter().find(|p| p.username == username) else {
+                    tracing::warn!("Player {} not found for door command", username);
+                    continue;
+                };
+                let Some(room) = rooms.get(&player.room) else {
+                    tracing::warn!("Current room {} for player {} not found", player.room, username);
+                    continue;
+                };
+                let Some((exit, door)) = room.find_door(&target).and_then(|exit| Some((exit, exit.door.clone()?))) else {
+                    let message = if room.exits.iter().all(|exit| exit.door.is_none()) {
+                        "There is no door here.".to_string()
+                    } else if target.trim().is_empty() || target.trim().eq_ignore_ascii_case("door") {
+                        format!("Which door do you want to {}?", action.verb())
+                    } else {
+                        "There is no door there.".to_string()
+                    };
+                    send_to(&player.player_tx, MudMessage::Notice { message });
+                    continue;
+                };
+
+                let Some(key) = door_id(&rooms, &player.room, exit) else {
+                    tracing::warn!("Door {} in room {} has no id", door.name, player.room);
+                    send_to(&player.player_tx, MudMessage::Notice { message: "There is no door there.".to_string() });
+                    continue;
+                };
+                let state = doors.get(&key).copied().unwrap_or(door.state);
+                let has_key = door.key.as_ref().is_some_and(|k| player.inventory.iter().any(|item| item.is_called(k)));
+                let new_state = match door_after(&door, state, action, has_key) {
+                    Ok(new_state) => new_state,
+                    Err(message) => {
+                        send_to(&player.player_tx, MudMessage::Notice { message });
+                        continue;
+                    }
+                };
+                doors.insert(key.clone(), new_state);
+                if let Some(closer) = door_closers.remove(&key) {
+                    scheduler.cancel(closer);
+                }
+                if new_state == DoorState::Open
+                    && let Some(secs) = door.closes_after
+                {
+                    let closer = scheduler.after(Duration::from_secs(secs), WorldEvent::CloseDoor { id: key.clone(), name: door.name.clone() });
+                    door_closers.insert(key.clone(), closer);
+                }
+                tracing::info!("Player {} used {} on {} in room {}: now {}", username, action.verb(), door.name, player.room, new_state);
+                let message = format!("You {} the {}.", action.verb(), door.name);
+                send_to(&player.player_tx, MudMessage::Notice { message });
+
+                // The room sees who did it; the other side only hears it
+                let other_sides = door_sides(&rooms, &key);
+                for p in players.iter().filter(|p| p.username != username) {
+                    let by = if p.room == player.room {
+                        Some(username.clone())
+                    } else if other_sides.contains(&p.room) {
+                        None
+                    } else {
+                        continue;
+                    };
+                    send_to(&p.player_tx, MudMessage::DoorChanged { door: door.name.clone(), action, username: by });
+                }
+            }
+            WorldCommand::GetItem { username, item } => pick_up(&mut players, &mut room_items, &username, &item),
+            WorldCommand::DropItem { username, item } => put_down(&mut players, &mut room_items, &username, &item),
+            WorldCommand::GiveItem { username, item, recipient } => hand_over(&mut players, &username, &item, &recipient),
+            WorldCommand::Examine { username, item } => {
+                let Some(player) = players.iter().find(|p| p.username == username) else {
+                    tracing::warn!("Player {} not found for examine command", username);
+                    continue;
+                };
+                // What they're carrying first, then what's lying around
+                let found = player.inventory.iter()
+                    .chain(room_items.get(&player.room).into_iter().flatten())
+                    .find(|i| i.is_called(&item));
+                let npc = npcs.iter().find(|n| n.room == player.room && n.npc.name.eq_ignore_ascii_case(item.trim()));
+                let message = match (found, npc) {
+                    (Some(found), _) => MudMessage::ItemDetails { item: found.clone() },
+                    (None, Some(npc)) if !npc.npc.description.is_empty() => MudMessage::Notice { message: npc.npc.description.clone() },
+                    (None, Some(npc)) => MudMessage::Notice { message: format!("You see nothing special about {}.", npc.npc.name) },
+                    (None, None) => MudMessage::Notice { message: format!("You don't see a {} here.", item.trim()) },
+                };
+                send_to(&player.player_tx, message);
+            }
+            WorldCommand::Inventory { username } => {
+                let Some(player) = players.iter().find(|p| p.username == username) else {
+                    tracing::warn!("Player {} not found for inventory command", username);
+                    continue;
+                };
+                send_to(&player.player_tx, MudMessage::InventoryList { items: player.inventory.clone() });
+            }
             WorldCommand::Speak { username, message } => {
                 // Find the player
                 let Some(player) = players.iter().find(|p| p.username == username) else {
@@ -148,42 +938,197 @@ async fn main_loop(
                 };
                 // Send the speak message to all players in the same room
                 for p in players.iter().filter(|p| p.room == player.room) {
-                    let _ = p.player_tx.send(MudMessage::PlayerSpeak { username: username.clone(), message: message.clone() }).await;
+                    send_to(&p.player_tx, MudMessage::PlayerSpeak { username: username.clone(), message: message.clone() });
                 }
                 tracing::info!("Player {} said in room {}: {}", username, player.room, message);
+
+                // NPCs answer anything they have a response to
+                for npc in npcs.iter().filter(|n| n.room == player.room) {
+                    let Some(reply) = npc.npc.reply_to(&message) else {
+                        continue;
+                    };
+                    for p in players.iter().filter(|p| p.room == player.room) {
+                        send_to(&p.player_tx, MudMessage::NpcSpeak { name: npc.npc.name.clone(), message: reply.to_string() });
+                    }
+                }
+            }
+            WorldCommand::Tick { scheduled } => {
+                metrics.record(std::mem::take(&mut busy), scheduled.elapsed(), tick_period);
+                refresh_behind(&rooms, &doors, &room_items, &npcs, &players);
+                for event in scheduler.advance() {
+                    match event {
+                        WorldEvent::NpcWander => {
+                            for npc in npcs.iter_mut().filter(|n| n.npc.wander > 0) {
+                                if !rand::rng().random_bool(NPC_WANDER_CHANCE) {
+                                    continue;
+                                }
+                                // Anywhere in range that isn't behind a closed door
+                                let Some(room) = rooms.get(&npc.room) else {
+                                    continue;
+                                };
+                                let ways: Vec<_> = room.exits.iter()
+                                    .filter(|exit| npc.range.contains(&exit.room_name))
+                                    .filter(|exit| door_state(&rooms, &doors, &npc.room, exit).is_none_or(|state| state == DoorState::Open))
+                                    .collect();
+                                let Some(exit) = ways.choose(&mut rand::rng()) else {
+                                    continue;
+                                };
+
+                                for p in players.iter().filter(|p| p.room == npc.room) {
+                                    send_to(&p.player_tx, MudMessage::NpcLeftRoom { name: npc.npc.name.clone(), direction: exit.direction.clone() });
+                                }
+                                tracing::debug!("NPC {} wandered from {} to {} (home {})", npc.npc.name, npc.room, exit.room_name, npc.home);
+                                npc.room = exit.room_name.clone();
+                                for p in players.iter().filter(|p| p.room == npc.room) {
+                                    send_to(&p.player_tx, MudMessage::NpcEnteredRoom { name: npc.npc.name.clone() });
+                                }
+                            }
+                        }
+                        WorldEvent::CloseDoor { id, name } => {
+                            door_closers.remove(&id);
+                            if doors.get(&id) != Some(&DoorState::Open) {
+                                continue;
+                            }
+                            doors.insert(id.clone(), DoorState::Closed);
+                            tracing::info!("Door {} ({}) swung shut", name, id);
+                            let sides = door_sides(&rooms, &id);
+                            for p in players.iter().filter(|p| sides.contains(&p.room)) {
+                                send_to(&p.player_tx, MudMessage::Notice { message: format!("The {name} swings shut.") });
+                            }
+                        }
+                        WorldEvent::RespawnItems => {
+                            let before: HashMap<String, usize> = room_items.iter().map(|(name, items)| (name.clone(), items.len())).collect();
+                            room_items = items_by_room(&rooms, std::mem::take(&mut room_items), carried(&players, &offline));
+                            for (room, items) in &room_items {
+                                let respawned = &items[before.get(room).copied().unwrap_or(0).min(items.len())..];
+                                if respawned.is_empty() {
+                                    continue;
+                                }
+                                tracing::debug!("{} item(s) respawned in {}", respawned.len(), room);
+                                for p in players.iter().filter(|p| p.room == *room) {
+                                    for item in respawned {
+                                        send_to(&p.player_tx, MudMessage::Notice { message: format!("A {} appears.", item.name) });
+                                    }
+                                }
+                            }
+                        }
+                        WorldEvent::TimeOfDay => {
+                            time_of_day = time_of_day.next();
+                            tracing::info!("Time of day is now {:?}", time_of_day);
+                            for p in &players {
+                                send_to(&p.player_tx, MudMessage::Notice { message: time_of_day.announcement().to_string() });
+                            }
+                        }
+                        WorldEvent::SaveAll => {
+                            save_players(players.iter().map(Player::state).collect());
+                        }
+                        WorldEvent::ReportTicks => metrics.report(),
+                    }
+                }
+            }
+            WorldCommand::ReplaceWorld { rooms: new_rooms } => {
+                let old_rooms = std::mem::replace(&mut rooms, new_rooms);
+                starting_rooms = find_starting_rooms(&rooms);
+                doors = door_states(&rooms, &doors);
+                room_items = items_by_room(&rooms, std::mem::take(&mut room_items), carried(&players, &offline));
+                npcs = spawn_npcs(&rooms, std::mem::take(&mut npcs));
+                tracing::info!("World replaced: {} rooms, {} starting rooms", rooms.len(), starting_rooms.len());
+
+                // Players whose room is gone go to a start room; players
+                // whose room changed just need to see it again
+                let mut moved = Vec::new();
+                let mut changed = Vec::new();
+                for player in players.iter_mut() {
+                    if rooms.contains_key(&player.room) {
+                        if old_rooms.get(&player.room) != rooms.get(&player.room) {
+                            changed.push(player.username.clone());
+                        }
+                        continue;
+                    }
+                    let Some(start) = starting_rooms.choose(&mut rand::rng()) else {
+                        tracing::error!("No starting room to move player {} to", player.username);
+                        continue;
+                    };
+                    let old_room = std::mem::replace(&mut player.room, start.clone());
+                    tracing::info!("Player {} moved from removed room {} to {}", player.username, old_room, start);
+                    moved.push((player.username.clone(), old_room));
+                }
+
+                for (username, old_room) in &moved {
+                    let Some(player) = players.iter().find(|p| p.username == *username) else {
+                        continue;
+                    };
+                    let notice = format!("{old_room} is no longer part of the world, so you have been moved to {}.", player.room);
+                    send_to(&player.player_tx, MudMessage::Notice { message: notice });
+                    for p in players.iter().filter(|p| p.room == player.room && !moved.iter().any(|(m, _)| *m == p.username)) {
+                        send_to(&p.player_tx, MudMessage::PlayerEnteredRoom { username: username.clone() });
+                    }
+                }
+                for username in moved.into_iter().map(|(username, _)| username).chain(changed) {
+                    let Some(player) = players.iter().find(|p| p.username == username) else {
+                        continue;
+                    };
+                    if let Some(message) = enter_room(&rooms, &doors, &room_items, &npcs, &players, &player.room, &username) {
+                        send_to(&player.player_tx, message);
+                    }
+                }
             }
         }
     }
 }
 
-pub async fn find_starting_room() -> anyhow::Result<String> {
+/// Reloads the world from where it was first loaded. The current world is
+/// kept if the new one fails to load or validate. Returns the room count.
+pub async fn reload() -> anyhow::Result<usize> {
     let tx = WORLD_COMMAND_TX.get().ok_or_else(|| anyhow::anyhow!("World command channel not initialized"))?;
-    let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
-    
-    tx.send(WorldCommand::FindStartingRoom { reply: reply_tx })
+    let path = ROOMS_PATH.get().ok_or_else(|| anyhow::anyhow!("Rooms path not initialized"))?.clone();
+    let rooms = tokio::task::spawn_blocking(move || load_world(&path)).await??;
+    let count = rooms.len();
+
+    tx.send(WorldCommand::ReplaceWorld { rooms })
         .await
-        .map_err(|_| anyhow::anyhow!("Failed to send command to world manager"))?;
-    
-    reply_rx.await.map_err(|_| anyhow::anyhow!("Failed to receive starting room"))
+        .map_err(|_| anyhow::anyhow!("Failed to send world replace command"))?;
+
+    Ok(count)
 }
 
-pub async fn spawn_player(username: &str, room: &str, player_tx: Sender<MudMessage>) -> anyhow::Result<()> {
+/// Puts a player into the world where they last left it. If they're still
+/// in the world, e.g. their connection dropped, the new connection takes them
+/// over and this returns true. Fails if their saved progress can't be read,
+/// rather than starting them over.
+pub async fn spawn_player(username: &str, player_tx: Sender<MudMessage>, connection_id: u64) -> anyhow::Result<bool> {
     let tx = WORLD_COMMAND_TX.get().ok_or_else(|| anyhow::anyhow!("World command channel not initialized"))?;
-    
+    let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+
     tx.send(WorldCommand::PlayerSpawn {
         username: username.to_string(),
-        room: room.to_string(),
         player_tx,
+        connection_id,
+        reply: reply_tx,
     }).await.map_err(|_| anyhow::anyhow!("Failed to send player spawn command"))?;
-    
+
+    reply_rx.await.map_err(|_| anyhow::anyhow!("Failed to receive player spawn reply"))?
+}
+
+/// Marks a player's connection as dropped. They stay in the world for
+/// [`RESUME_GRACE`] in case the client reconnects.
+pub async fn detach_player(username: &str, connection_id: u64) -> anyhow::Result<()> {
+    let tx = WORLD_COMMAND_TX.get().ok_or_else(|| anyhow::anyhow!("World command channel not initialized"))?;
+
+    tx.send(WorldCommand::DetachPlayer {
+        username: username.to_string(),
+        connection_id,
+    }).await.map_err(|_| anyhow::anyhow!("Failed to send player detach command"))?;
+
     Ok(())
 }
 
-pub async fn despawn_player(username: &str) -> anyhow::Result<()> {
+pub async fn despawn_player(username: &str, connection_id: u64) -> anyhow::Result<()> {
     let tx = WORLD_COMMAND_TX.get().ok_or_else(|| anyhow::anyhow!("World command channel not initialized"))?;
     
     tx.send(WorldCommand::DespawnPlayer {
         username: username.to_string(),
+        connection_id,
     }).await.map_err(|_| anyhow::anyhow!("Failed to send player despawn command"))?;
     
     Ok(())
@@ -200,6 +1145,73 @@ pub async fn move_player(username: &str, direction: &str) -> anyhow::Result<()>
     Ok(())
 }
 
+pub async fn operate_door(username: &str, action: DoorAction, target: &str) -> anyhow::Result<()> {
+    let tx = WORLD_COMMAND_TX.get().ok_or_else(|| anyhow::anyhow!("World command channel not initialized"))?;
+
+    tx.send(WorldCommand::OperateDoor {
+        username: username.to_string(),
+        action,
+        target: target.to_string(),
+    }).await.map_err(|_| anyhow::anyhow!("Failed to send door command"))?;
+
+    Ok(())
+}
+
+pub async fn get_item(username: &str, item: &str) -> anyhow::Result<()> {
+    let tx = WORLD_COMMAND_TX.get().ok_or_else(|| anyhow::anyhow!("World command channel not initialized"))?;
+
+    tx.send(WorldCommand::GetItem {
+        username: username.to_string(),
+        item: item.to_string(),
+    }).await.map_err(|_| anyhow::anyhow!("Failed to send get item command"))?;
+
+    Ok(())
+}
+
+pub async fn drop_item(username: &str, item: &str) -> anyhow::Result<()> {
+    let tx = WORLD_COMMAND_TX.get().ok_or_else(|| anyhow::anyhow!("World command channel not initialized"))?;
+
+    tx.send(WorldCommand::DropItem {
+        username: username.to_string(),
+        item: item.to_string(),
+    }).await.map_err(|_| anyhow::anyhow!("Failed to send drop item command"))?;
+
+    Ok(())
+}
+
+pub async fn give_item(username: &str, item: &str, recipient: &str) -> anyhow::Result<()> {
+    let tx = WORLD_COMMAND_TX.get().ok_or_else(|| anyhow::anyhow!("World command channel not initialized"))?;
+
+    tx.send(WorldCommand::GiveItem {
+        username: username.to_string(),
+        item: item.to_string(),
+        recipient: recipient.to_string(),
+    }).await.map_err(|_| anyhow::anyhow!("Failed to send give item command"))?;
+
+    Ok(())
+}
+
+pub async fn examine(username: &str, item: &str) -> anyhow::Result<()> {
+    let tx = WORLD_COMMAND_TX.get().ok_or_else(|| anyhow::anyhow!("World command channel not initialized"))?;
+
+    tx.send(WorldCommand::Examine {
+        username: username.to_string(),
+        item: item.to_string(),
+    }).await.map_err(|_| anyhow::anyhow!("Failed to send examine command"))?;
+
+    Ok(())
+}
+
+pub async fn inventory(username: &str) -> anyhow::Result<()> {
+    let tx = WORLD_COMMAND_TX.get().ok_or_else(|| anyhow::anyhow!("World command channel not initialized"))?;
+
+    tx.send(WorldCommand::Inventory { username: username.to_string() })
+        .await
+        .map_err(|_| anyhow::anyhow!("Failed to send inventory command"))?;
+
+    Ok(())
+}
+
 pub async fn player_speak(username: &str, message: &str) -> anyhow::Result<()> {
     let tx = WORLD_COMMAND_TX.get().ok_or_else(|| anyhow::anyhow!("World command channel not initialized"))?;
     
@@ -209,4 +1221,250 @@ pub async fn player_speak(username: &str, message: &str) -> anyhow::Result<()> {
     }).await.map_err(|_| anyhow::anyhow!("Failed to send player speak command"))?;
     
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(name: &str) -> Item {
+        Item { name: name.to_string(), description: String::new(), properties: Default::default() }
+    }
+
+    fn room(name: &str, items: &[&str]) -> (String, Room) {
+        let items = items.iter().map(|name| item(name)).collect();
+        (name.to_string(), Room { name: name.to_string(), description: String::new(), exits: Vec::new(), items, npcs: Vec::new(), start: true })
+    }
+
+    fn names(items: &[Item]) -> Vec<&str> {
+        items.iter().map(|item| item.name.as_str()).collect()
+    }
+
+    fn player(username: &str, room: &str, capacity: usize) -> (Player, Receiver<MudMessage>) {
+        let (player_tx, player_rx) = tokio::sync::mpsc::channel(capacity);
+        let player = Player {
+            username: username.to_string(),
+            room: room.to_string(),
+            player_tx: PlayerTx::new(player_tx),
+            connection_id: 1,
+            detached_at: None,
+            inventory: Vec::new(),
+            first_seen: 0,
+            play_time_secs: 0,
+            joined: Instant::now(),
+        };
+        (player, player_rx)
+    }
+
+    /// Everything waiting in a player's queue.
+    fn received(player_rx: &mut Receiver<MudMessage>) -> Vec<MudMessage> {
+        std::iter::from_fn(|| player_rx.try_recv().ok()).collect()
+    }
+
+    fn notices(player_rx: &mut Receiver<MudMessage>) -> Vec<String> {
+        received(player_rx)
+            .into_iter()
+            .map(|message| match message {
+                MudMessage::Notice { message } => message,
+                other => panic!("expected a notice, got {other:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_reload_keeps_items_in_play() {
+        let rooms: HashMap<String, Room> = [room("Hall", &["lamp", "coin"]), room("Study", &["book"])].into_iter().collect();
+        let mut items = items_by_room(&rooms, HashMap::new(), std::iter::empty());
+        assert_eq!(names(&items["Hall"]), ["lamp", "coin"]);
+
+        // A player picks up the lamp and drops the book in the hall; then a
+        // builder adds a second coin and a map, and the world is reloaded
+        let lamp = take_item(items.get_mut("Hall").unwrap(), "lamp").unwrap();
+        let book = take_item(items.get_mut("Study").unwrap(), "book").unwrap();
+        items.get_mut("Hall").unwrap().push(book);
+        let rooms: HashMap<String, Room> = [room("Hall", &["lamp", "coin", "coin", "map"]), room("Study", &["book"])].into_iter().collect();
+        let items = items_by_room(&rooms, items, [&lamp].into_iter());
+        assert_eq!(names(&items["Hall"]), ["coin", "book", "coin", "map"]);
+        assert!(items["Study"].is_empty());
+    }
+
+    #[test]
+    fn test_reload_counts_what_offline_players_carry() {
+        let rooms: HashMap<String, Room> = [room("Hall", &["lamp", "coin"])].into_iter().collect();
+        let mut items = items_by_room(&rooms, HashMap::new(), std::iter::empty());
+
+        // A player logs out with the lamp, then the world is reloaded
+        let lamp = take_item(items.get_mut("Hall").unwrap(), "lamp").unwrap();
+        let offline = HashMap::from([("pat".to_string(), vec![lamp])]);
+        let items = items_by_room(&rooms, items, carried(&[], &offline));
+        assert_eq!(names(&items["Hall"]), ["coin"]);
+    }
+
+    #[test]
+    fn test_respawn_replaces_items_out_of_play() {
+        let rooms: HashMap<String, Room> = [room("Hall", &["lamp", "coin"])].into_iter().collect();
+        let mut items = items_by_room(&rooms, HashMap::new(), std::iter::empty());
+
+        // A player is carrying the lamp; the coin has gone from play
+        let lamp = take_item(items.get_mut("Hall").unwrap(), "lamp").unwrap();
+        take_item(items.get_mut("Hall").unwrap(), "coin").unwrap();
+        let items = items_by_room(&rooms, items, [&lamp].into_iter());
+        assert_eq!(names(&items["Hall"]), ["coin"]);
+
+        // Nothing more comes back while everything is in play
+        let items = items_by_room(&rooms, items, [&lamp].into_iter());
+        assert_eq!(names(&items["Hall"]), ["coin"]);
+    }
+
+    #[test]
+    fn test_respawn_counts_what_offline_players_carry() {
+        let rooms: HashMap<String, Room> = [room("Hall", &["lamp"])].into_iter().collect();
+        let mut items = items_by_room(&rooms, HashMap::new(), std::iter::empty());
+        let (mut pat, _pat_rx) = player("pat", "Hall", 1);
+        pat.inventory.push(take_item(items.get_mut("Hall").unwrap(), "lamp").unwrap());
+        let mut players = vec![pat];
+        let mut offline = HashMap::new();
+
+        // Pat logs out with the lamp and the items respawn
+        remove_players(&mut players, &mut offline, |p| p.username == "pat");
+        let items = items_by_room(&rooms, items, carried(&players, &offline));
+        assert!(items["Hall"].is_empty());
+
+        // Pat logs back in, and there's still only the one lamp
+        let inventory = offline.remove("pat").unwrap();
+        assert_eq!(names(&inventory), ["lamp"]);
+        assert!(carried(&players, &offline).next().is_none());
+    }
+
+    #[test]
+    fn test_pick_up_and_put_down() {
+        let (pat, mut pat_rx) = player("pat", "Hall", 8);
+        let (bob, mut bob_rx) = player("bob", "Hall", 8);
+        let (sam, mut sam_rx) = player("sam", "Study", 8);
+        let mut players = vec![pat, bob, sam];
+        let rooms: HashMap<String, Room> = [room("Hall", &["lamp"]), room("Study", &[])].into_iter().collect();
+        let mut items = items_by_room(&rooms, HashMap::new(), std::iter::empty());
+
+        pick_up(&mut players, &mut items, "pat", "LAMP");
+        pick_up(&mut players, &mut items, "pat", "lamp");
+        assert_eq!(notices(&mut pat_rx), ["You pick up the lamp.", "There is no lamp here."]);
+        assert_eq!(names(&players[0].inventory), ["lamp"]);
+        assert!(items["Hall"].is_empty());
+        assert!(matches!(&received(&mut bob_rx)[..], [MudMessage::PlayerGotItem { username, item }] if username == "pat" && item == "lamp"));
+
+        put_down(&mut players, &mut items, "pat", "lamp");
+        put_down(&mut players, &mut items, "pat", "lamp");
+        assert_eq!(notices(&mut pat_rx), ["You drop the lamp.", "You aren't carrying a lamp."]);
+        assert!(players[0].inventory.is_empty());
+        assert_eq!(names(&items["Hall"]), ["lamp"]);
+        assert!(matches!(&received(&mut bob_rx)[..], [MudMessage::PlayerDroppedItem { username, item }] if username == "pat" && item == "lamp"));
+
+        // Nobody in another room hears about any of it
+        assert!(received(&mut sam_rx).is_empty());
+    }
+
+    #[test]
+    fn test_hand_over() {
+        let (mut pat, mut pat_rx) = player("pat", "Hall", 8);
+        let (bob, mut bob_rx) = player("bob", "Hall", 8);
+        let (amy, mut amy_rx) = player("amy", "Hall", 8);
+        let (sam, mut sam_rx) = player("sam", "Study", 8);
+        pat.inventory.push(item("lamp"));
+        let mut players = vec![pat, bob, amy, sam];
+
+        hand_over(&mut players, "pat", "lamp", "sam");
+        hand_over(&mut players, "pat", "lamp", "pat");
+        hand_over(&mut players, "pat", "coin", "bob");
+        assert_eq!(notices(&mut pat_rx), [
+            "There is nobody called sam here.",
+            "There is nobody called pat here.",
+            "You aren't carrying a coin.",
+        ]);
+
+        hand_over(&mut players, "pat", "lamp", " Bob ");
+        assert_eq!(notices(&mut pat_rx), ["You give the lamp to bob."]);
+        assert_eq!(notices(&mut bob_rx), ["pat gives you the lamp."]);
+        assert!(players[0].inventory.is_empty());
+        assert_eq!(names(&players[1].inventory), ["lamp"]);
+        assert!(matches!(
+            &received(&mut amy_rx)[..],
+            [MudMessage::PlayerGaveItem { username, item, recipient }] if username == "pat" && item == "lamp" && recipient == "bob"
+        ));
+        assert!(received(&mut sam_rx).is_empty());
+    }
+
+    #[test]
+    fn test_players_who_miss_messages_are_refreshed() {
+        let (mut pat, mut pat_rx) = player("pat", "Hall", REFRESH_MESSAGES);
+        pat.inventory.push(item("lamp"));
+        let players = vec![pat];
+        let rooms: HashMap<String, Room> = [room("Hall", &[])].into_iter().collect();
+        let items = items_by_room(&rooms, HashMap::new(), std::iter::empty());
+        let refresh = || refresh_behind(&rooms, &HashMap::new(), &items, &[], &players);
+
+        // Pat stops reading, so the last notice is dropped
+        for message in ["one", "two", "three"] {
+            send_to(&players[0].player_tx, MudMessage::Notice { message: message.to_string() });
+        }
+        refresh();
+        assert_eq!(notices(&mut pat_rx), ["one", "two"]);
+
+        // Once there's room, they see the world as it is now, just the once
+        refresh();
+        refresh();
+        assert!(matches!(
+            &received(&mut pat_rx)[..],
+            [MudMessage::EnterRoom { room, .. }, MudMessage::InventoryList { items }] if room.name == "Hall" && names(items) == ["lamp"]
+        ));
+    }
+
+    #[test]
+    fn test_door_after() {
+        use DoorAction::{Close, Lock, Unlock};
+        use DoorState::{Closed, Locked};
+        let locked = "The oak door is locked.";
+        let no_key = "You don't have the key to the oak door.";
+        type Outcome = Result<DoorState, &'static str>;
+        // What each action does from each state, with the key and without it
+        let table: [(DoorAction, DoorState, Outcome, Outcome); 12] = [
+            (DoorAction::Open, DoorState::Open, Err("The oak door is already open."), Err("The oak door is already open.")),
+            (DoorAction::Open, Closed, Ok(DoorState::Open), Ok(DoorState::Open)),
+            (DoorAction::Open, Locked, Err(locked), Err(locked)),
+            (Close, DoorState::Open, Ok(Closed), Ok(Closed)),
+            (Close, Closed, Err("The oak door is already closed."), Err("The oak door is already closed.")),
+            (Close, Locked, Err("The oak door is already closed."), Err("The oak door is already closed.")),
+            (Lock, DoorState::Open, Err("You need to close the oak door first."), Err("You need to close the oak door first.")),
+            (Lock, Closed, Ok(Locked), Err(no_key)),
+            (Lock, Locked, Err("The oak door is already locked."), Err("The oak door is already locked.")),
+            (Unlock, DoorState::Open, Err("The oak door isn't locked."), Err("The oak door isn't locked.")),
+            (Unlock, Closed, Err("The oak door isn't locked."), Err("The oak door isn't locked.")),
+            (Unlock, Locked, Ok(Closed), Err(no_key)),
+        ];
+        let mut door = Door { name: "oak door".to_string(), id: None, state: Closed, key: Some("brass key".to_string()), closes_after: None };
+        for (action, state, with_key, without_key) in table {
+            let expected = [(true, with_key), (false, without_key)];
+            for (has_key, expected) in expected {
+                assert_eq!(door_after(&door, state, action, has_key), expected.map_err(str::to_string), "{action:?} from {state:?}, key {has_key}");
+            }
+        }
+
+        // A door without a key opens and closes the same, but has no lock
+        door.key = None;
+        for (action, state, expected, _) in table {
+            let expected = match action {
+                Lock | Unlock => Err("The oak door has no lock.".to_string()),
+                DoorAction::Open | Close => expected.map_err(str::to_string),
+            };
+            for has_key in [true, false] {
+                assert_eq!(door_after(&door, state, action, has_key), expected, "{action:?} from {state:?} without a lock");
+            }
+        }
+    }
+
+    #[test]
+    fn test_time_of_day_cycles() {
+        let mut time = TimeOfDay::Dawn;
+        let day: Vec<TimeOfDay> = (0..4).map(|_| { time = time.next(); time }).collect();
+        assert_eq!(day, [TimeOfDay::Day, TimeOfDay::Dusk, TimeOfDay::Night, TimeOfDay::Dawn]);
+    }
+}